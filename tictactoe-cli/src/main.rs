@@ -6,17 +6,21 @@ use concordium_rust_sdk::{
     endpoints,
     id::types::{AccountAddress, AccountKeys},
     types::{
+        hashes::BlockHash,
         smart_contracts::{
             concordium_contracts_common::{
-                from_bytes, Amount, ContractAddress, OwnedContractName, OwnedReceiveName, Serialize,
+                from_bytes, to_bytes, Address, Amount, ContractAddress, Deserial, Duration,
+                OwnedContractName, OwnedEntrypointName, OwnedReceiveName, Serial, Serialize,
+                Timestamp, Write,
             },
-            ModuleRef, Parameter, WasmModule,
+            ContractContext, InvokeContractResult, ModuleRef, Parameter, WasmModule,
         },
         transactions::{send, BlockItem, InitContractPayload, UpdateContractPayload},
-        AccountInfo,
+        AccountInfo, Nonce,
     },
 };
-use std::path::PathBuf;
+use sha2::Digest;
+use std::{convert::TryInto, path::PathBuf};
 use structopt::*;
 
 #[derive(StructOpt)]
@@ -54,17 +58,106 @@ enum Action {
         #[structopt(long, help = "The module references to initialize.")]
         module_ref: ModuleRef,
     },
+    #[structopt(about = "Upgrade the contract to a new module, keeping its games")]
+    Upgrade {
+        #[structopt(long, help = "The contract address")]
+        address:    ContractAddress,
+        #[structopt(long, help = "The deployed module to upgrade to.")]
+        module_ref: ModuleRef,
+        #[structopt(long, help = "Skip migrating the state, if its layout did not change.")]
+        no_migrate: bool,
+    },
+    #[structopt(about = "Convert the state to the layout of the current module")]
+    Migrate {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+    },
     #[structopt(about = "Create a game")]
     CreateGame {
         #[structopt(long, help = "The contract address")]
-        address: ContractAddress,
+        address:    ContractAddress,
+        #[structopt(long, help = "The CCD to stake on the game", default_value = "0")]
+        stake:      Amount,
+        #[structopt(flatten)]
+        settings:   GameSettings,
+        #[structopt(long, help = "Draw who plays first instead of starting yourself")]
+        draw_seats: bool,
+        #[structopt(flatten)]
+        tokens:     TokenStakeOptions,
+    },
+    #[structopt(about = "Create a match of several games against the same opponent")]
+    CreateMatch {
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+        #[structopt(long, help = "The most games the match lasts", default_value = "3")]
+        best_of:  u32,
+        #[structopt(flatten)]
+        settings: GameSettings,
+    },
+    #[structopt(about = "Join a match, which starts its first game")]
+    JoinMatch {
+        #[structopt(long, help = "The match to join")]
+        the_match: u64,
+        #[structopt(long, help = "The contract address")]
+        address:   ContractAddress,
+    },
+    #[structopt(about = "Show the score and the games of a match")]
+    ViewMatch {
+        #[structopt(long, help = "The match to show")]
+        the_match: u64,
+        #[structopt(long, help = "The contract address")]
+        address:   ContractAddress,
+    },
+    #[structopt(about = "Create a single-elimination tournament")]
+    CreateTournament {
+        #[structopt(long, help = "The contract address")]
+        address:   ContractAddress,
+        #[structopt(long, help = "The number of players, a power of two")]
+        size:      u32,
+        #[structopt(long, help = "The CCD each player pays to register", default_value = "0")]
+        entry_fee: Amount,
+        #[structopt(flatten)]
+        settings:  GameSettings,
+    },
+    #[structopt(about = "Register for a tournament, paying its entry fee")]
+    Register {
+        #[structopt(long, help = "The tournament to register for")]
+        the_tournament: u64,
+        #[structopt(long, help = "The contract address")]
+        address:        ContractAddress,
+        #[structopt(long, help = "The entry fee of the tournament", default_value = "0")]
+        entry_fee:      Amount,
+    },
+    #[structopt(about = "Call off a tournament that is not full yet")]
+    CancelTournament {
+        #[structopt(long, help = "The tournament to cancel")]
+        the_tournament: u64,
+        #[structopt(long, help = "The contract address")]
+        address:        ContractAddress,
+    },
+    #[structopt(about = "Show the players, the bracket and the games of a tournament")]
+    ViewTournament {
+        #[structopt(long, help = "The tournament to show")]
+        the_tournament: u64,
+        #[structopt(long, help = "The contract address")]
+        address:        ContractAddress,
     },
     #[structopt(about = "Join a game")]
     JoinGame {
         #[structopt(long, help = "The game to join")]
-        the_game: u64,
+        the_game:   u64,
         #[structopt(long, help = "The contract address")]
-        address:  ContractAddress,
+        address:    ContractAddress,
+        #[structopt(
+            long,
+            help = "The CCD to stake, must match the stake of the game",
+            default_value = "0"
+        )]
+        stake:      Amount,
+        #[structopt(long, help = "Commit to a secret, needed if the creator draws the seats")]
+        draw_seats: bool,
+        #[structopt(flatten)]
+        tokens:     TokenStakeOptions,
     },
     #[structopt(about = "Make a move")]
     Move {
@@ -75,9 +168,122 @@ enum Action {
         #[structopt(long, help = "The contract address")]
         address:  ContractAddress,
     },
+    #[structopt(about = "Reveal your secret to draw who plays first")]
+    Reveal {
+        #[structopt(long, help = "The game to reveal the secret for")]
+        the_game: u64,
+        #[structopt(long, help = "The secret printed when creating or joining the game")]
+        secret:   Secret,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Win a game where the opponent did not move in time")]
+    ClaimTimeout {
+        #[structopt(long, help = "The game to claim")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Resign a game, letting the opponent win")]
+    Resign {
+        #[structopt(long, help = "The game to resign")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Cancel a game nobody has joined yet")]
+    CancelGame {
+        #[structopt(long, help = "The game to cancel")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Propose to abort a game")]
+    ProposeAbort {
+        #[structopt(long, help = "The game to abort")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Accept the proposal of the opponent to abort a game")]
+    AcceptAbort {
+        #[structopt(long, help = "The game to abort")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Offer the opponent to end a game in a draw")]
+    OfferDraw {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Accept the draw offered by the opponent")]
+    AcceptDraw {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Turn down the draw offered by the opponent")]
+    DeclineDraw {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Ask the opponent to let you take back your last move")]
+    RequestTakeback {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Let the opponent take back their last move")]
+    AcceptTakeback {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Move a channel game back on-chain from its on-chain position")]
+    Dispute {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "Let a bot contract play your games")]
+    SetBot {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "The bot contract, leave out to play in person again")]
+        bot:     Option<ContractAddress>,
+    },
+    #[structopt(about = "Let the bots whose turn it is in a game make their moves")]
+    PlayBotTurn {
+        #[structopt(long, help = "The game")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+    },
+    #[structopt(about = "List the games, one page at a time")]
     ViewState {
         #[structopt(long, help = "The contract address")]
         address: ContractAddress,
+        #[structopt(long, help = "The game to start listing from", default_value = "0")]
+        start:   u64,
+        #[structopt(long, help = "The maximum number of games to list", default_value = "20")]
+        limit:   u32,
+        #[structopt(
+            long,
+            help = "Only list games in this state: awaiting-opponent, in-progress, finished, \
+                    cancelled, aborted or awaiting-reveal"
+        )]
+        state:   Option<GameStateKind>,
+        #[structopt(long, help = "Only list the games of this account")]
+        player:  Option<AccountAddress>,
     },
     ViewAccounts {
         #[structopt(long, help = "The game to join")]
@@ -85,6 +291,294 @@ enum Action {
         #[structopt(long, help = "The contract address")]
         address:  ContractAddress,
     },
+    #[structopt(about = "Show the moves of a game in the order they were made")]
+    History {
+        #[structopt(long, help = "The game to show")]
+        the_game: u64,
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+        #[structopt(long, help = "The move to start from, counting from 0", default_value = "0")]
+        start:    u32,
+        #[structopt(long, help = "The maximum number of moves to show", default_value = "100")]
+        limit:    u32,
+    },
+    #[structopt(about = "List the ids of the games an account plays in")]
+    MyGames {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "The account to look up, instead of your own")]
+        player:  Option<AccountAddress>,
+    },
+    #[structopt(about = "Show the nonce the next move permit of an account has to carry")]
+    Nonce {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "The account to look up, instead of your own")]
+        player:  Option<AccountAddress>,
+    },
+    #[structopt(about = "Show the results and rating of an account")]
+    PlayerStats {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "The account to look up, instead of your own")]
+        player:  Option<AccountAddress>,
+    },
+    #[structopt(about = "Show the highest rated accounts")]
+    Leaderboard {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "The number of accounts to show", default_value = "10")]
+        top:     u32,
+    },
+    #[structopt(about = "Archive finished games to free up storage")]
+    PruneFinished {
+        #[structopt(long, help = "The contract address")]
+        address:    ContractAddress,
+        #[structopt(
+            long,
+            help = "Only archive games that ended at least this long ago",
+            default_value = "30d"
+        )]
+        older_than: Duration,
+        #[structopt(long, help = "The game id to start from", default_value = "0")]
+        start:      u64,
+    },
+    #[structopt(about = "Hand the contract over to another account")]
+    TransferOwnership {
+        #[structopt(long, help = "The contract address")]
+        address:   ContractAddress,
+        #[structopt(long, help = "The account to become the owner")]
+        new_owner: AccountAddress,
+    },
+    #[structopt(about = "Grant or revoke the admin role")]
+    SetAdmin {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "The account to grant the role to")]
+        account: AccountAddress,
+        #[structopt(long, help = "Revoke the role instead")]
+        revoke:  bool,
+    },
+    #[structopt(about = "Pause the contract, or unpause it")]
+    SetPaused {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+        #[structopt(long, help = "Unpause the contract instead")]
+        unpause: bool,
+    },
+    #[structopt(about = "Allow games to be staked in a CIS-2 token, or disallow it")]
+    SetTokenAllowed {
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+        #[structopt(long, help = "The CIS-2 token contract")]
+        token:    ContractAddress,
+        #[structopt(long, help = "The id of the token, in hex")]
+        token_id: String,
+        #[structopt(long, help = "Disallow the token instead")]
+        remove:   bool,
+    },
+    #[structopt(about = "Set where the metadata of the trophies is found")]
+    SetMetadataUrl {
+        #[structopt(long, help = "The contract address")]
+        address:  ContractAddress,
+        #[structopt(long, help = "The URL the token id of a trophy is appended to in hex")]
+        base_url: String,
+    },
+    #[structopt(about = "Show who holds the trophy of a game, who lost it and the final board")]
+    ViewTrophy {
+        #[structopt(long, help = "The game the trophy was won in")]
+        game:    u64,
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+    },
+    #[structopt(about = "Show the owner and admins of the contract, and whether it is paused")]
+    ViewRoles {
+        #[structopt(long, help = "The contract address")]
+        address: ContractAddress,
+    },
+}
+
+/// Stake CIS-2 tokens on a game instead of CCD. The tokens are transferred to
+/// the 'onReceivingCIS2' entrypoint of the tictactoe contract.
+#[derive(StructOpt)]
+struct TokenStakeOptions {
+    #[structopt(long, help = "Stake tokens of this CIS-2 contract instead of CCD")]
+    token:               Option<ContractAddress>,
+    #[structopt(
+        long,
+        help = "The contract name of the token contract",
+        default_value = "cis2_multi"
+    )]
+    token_contract_name: String,
+    #[structopt(long, help = "The id of the token to stake, in hex", default_value = "")]
+    token_id:            String,
+    #[structopt(long, help = "The amount of tokens to stake", default_value = "0")]
+    token_amount:        u64,
+}
+
+impl TokenStakeOptions {
+    /// The payload transferring the tokens from 'from' to the tictactoe
+    /// contract at 'address', which then carries out 'action'.
+    fn payload(
+        &self,
+        from: AccountAddress,
+        address: ContractAddress,
+        action: &TokenStakeAction,
+    ) -> anyhow::Result<UpdateContractPayload> {
+        let token = self.token.context("No token contract given.")?;
+        let transfer = TokenTransfer {
+            token_id: hex::decode(&self.token_id).context("The token id must be hex encoded.")?,
+            amount: self.token_amount,
+            from,
+            to: address,
+            data: to_bytes(action),
+        };
+        Ok(UpdateContractPayload {
+            amount:       Amount::zero(),
+            address:      token,
+            receive_name: OwnedReceiveName::new_unchecked(format!(
+                "{}.transfer",
+                self.token_contract_name
+            )),
+            message:      Parameter::from(to_bytes(&transfer)),
+        })
+    }
+}
+
+/// What the tictactoe contract does with the staked tokens.
+#[derive(Serialize)]
+enum TokenStakeAction {
+    CreateGame(CreateGameParams),
+    JoinGame(JoinGameParams),
+}
+
+/// The parameter of a CIS-2 'transfer' of tokens to the 'onReceivingCIS2'
+/// entrypoint of the tictactoe contract.
+struct TokenTransfer {
+    token_id: Vec<u8>,
+    amount:   u64,
+    from:     AccountAddress,
+    to:       ContractAddress,
+    data:     Vec<u8>,
+}
+
+impl Serial for TokenTransfer {
+    fn serial<W: Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        // A list of a single transfer.
+        1u16.serial(out)?;
+        (self.token_id.len() as u8).serial(out)?;
+        out.write_all(&self.token_id)?;
+        // The amount is LEB128 encoded.
+        let mut amount = self.amount;
+        while amount >= 0x80 {
+            ((amount & 0x7f) as u8 | 0x80).serial(out)?;
+            amount >>= 7;
+        }
+        (amount as u8).serial(out)?;
+        Address::Account(self.from).serial(out)?;
+        // The receiver is the contract, notified through 'onReceivingCIS2'.
+        1u8.serial(out)?;
+        self.to.serial(out)?;
+        OwnedEntrypointName::new_unchecked("onReceivingCIS2".into()).serial(out)?;
+        (self.data.len() as u16).serial(out)?;
+        out.write_all(&self.data)
+    }
+}
+
+/// How a game is played, shared by games and matches.
+#[derive(StructOpt)]
+struct GameSettings {
+    #[structopt(long, help = "The time each player has for a move", default_value = "1d")]
+    move_timeout: Duration,
+    #[structopt(long, help = "Only allow this account to join")]
+    opponent:     Option<AccountAddress>,
+    #[structopt(long, help = "The number of rows and columns", default_value = "3")]
+    board_size:   u8,
+    #[structopt(
+        long,
+        help = "The marks in a row needed to win, defaults to the board size but at most 5"
+    )]
+    win_length:   Option<u8>,
+    #[structopt(long, help = "Play ultimate tic-tac-toe on nine sub-boards")]
+    ultimate:     bool,
+    #[structopt(long, help = "Play the moves off-chain and only submit the final board")]
+    channel:      bool,
+}
+
+impl GameSettings {
+    fn into_params(self, commitment: Option<CreatorCommitment>) -> CreateGameParams {
+        CreateGameParams {
+            move_timeout: self.move_timeout,
+            opponent: self.opponent,
+            board_size: self.board_size,
+            win_length: self.win_length.unwrap_or_else(|| self.board_size.min(5)),
+            variant: if self.ultimate {
+                GameVariant::Ultimate
+            } else {
+                GameVariant::Classic
+            },
+            commitment,
+            channel: self.channel,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CreateGameParams {
+    move_timeout: Duration,
+    opponent:     Option<AccountAddress>,
+    board_size:   u8,
+    win_length:   u8,
+    variant:      GameVariant,
+    commitment:   Option<CreatorCommitment>,
+    channel:      bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatorCommitment {
+    game_id:    u64,
+    commitment: [u8; 32],
+}
+
+#[derive(Debug, Serialize)]
+enum GameVariant {
+    Classic,
+    Ultimate,
+}
+
+#[derive(Serialize)]
+struct CreateMatchParams {
+    best_of:  u32,
+    settings: CreateGameParams,
+}
+
+#[derive(Serialize)]
+struct MatchParams {
+    match_id: u64,
+}
+
+#[derive(Serialize)]
+struct CreateTournamentParams {
+    size:      u32,
+    entry_fee: Amount,
+    settings:  CreateGameParams,
+}
+
+#[derive(Serialize)]
+struct TournamentParams {
+    tournament_id: u64,
+}
+
+#[derive(Serialize)]
+struct JoinGameParams {
+    game_id:    u64,
+    commitment: Option<[u8; 32]>,
+}
+
+#[derive(Serialize)]
+struct RevealParams {
+    game_id: u64,
+    secret:  [u8; 32],
 }
 
 #[derive(Serialize)]
@@ -92,6 +586,126 @@ struct JoinParams {
     game_id: u64,
 }
 
+#[derive(Serialize)]
+struct ListGamesParams {
+    start:   u64,
+    limit:   u32,
+    state:   Option<GameStateKind>,
+    account: Option<AccountAddress>,
+}
+
+#[derive(Serialize)]
+struct HistoryParams {
+    game_id: u64,
+    start:   u32,
+    limit:   u32,
+}
+
+#[derive(Serialize)]
+struct AccountParams {
+    account: AccountAddress,
+}
+
+#[derive(Serialize)]
+struct UpgradeParams {
+    module:  [u8; 32],
+    /// The entrypoint to call after the upgrade, and the length of its
+    /// parameter. 'migrate' takes no parameter, so the length is always 0.
+    migrate: Option<(OwnedEntrypointName, u16)>,
+}
+
+#[derive(Serialize)]
+struct LeaderboardParams {
+    top: u32,
+}
+
+#[derive(Serialize)]
+struct DisputeParams {
+    game_id: u64,
+    /// The CLI does not sign channel states, so it only disputes from the
+    /// position on-chain.
+    signed:  Option<()>,
+}
+
+#[derive(Serialize)]
+struct SetBotParams {
+    bot: Option<ContractAddress>,
+}
+
+#[derive(Serialize)]
+struct PruneParams {
+    start:      u64,
+    older_than: Duration,
+}
+
+#[derive(Serialize)]
+struct TransferOwnershipParams {
+    new_owner: AccountAddress,
+}
+
+#[derive(Serialize)]
+struct SetAdminParams {
+    account:  AccountAddress,
+    is_admin: bool,
+}
+
+#[derive(Serialize)]
+struct SetPausedParams {
+    paused: bool,
+}
+
+/// The parameter of 'set_token_allowed'. The token id is a byte list with a
+/// one byte length, like in CIS-2.
+struct SetTokenAllowedParams {
+    contract: ContractAddress,
+    token_id: Vec<u8>,
+    allowed:  bool,
+}
+
+impl Serial for SetTokenAllowedParams {
+    fn serial<W: Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        self.contract.serial(out)?;
+        (self.token_id.len() as u8).serial(out)?;
+        out.write_all(&self.token_id)?;
+        self.allowed.serial(out)
+    }
+}
+
+#[derive(Serialize)]
+struct SetMetadataUrlParams {
+    base_url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Players {
+    cross:  AccountAddress,
+    circle: Option<AccountAddress>,
+}
+
+#[derive(Debug, Serialize)]
+struct RolesView {
+    owner:   AccountAddress,
+    admins:  Vec<AccountAddress>,
+    paused:  bool,
+    version: u32,
+}
+
+/// The cells are packed four to a byte, two bits each: 0 when empty, 1 for
+/// cross and 2 for circle.
+#[derive(Debug, Serialize)]
+struct Board {
+    size:       u8,
+    win_length: u8,
+    cells:      Vec<u8>,
+}
+
+#[derive(Debug, Serialize)]
+struct Trophy {
+    owner:    Address,
+    opponent: AccountAddress,
+    board:    Board,
+}
+
 #[derive(Serialize)]
 struct MakeMoveParams {
     game_id:  u64,
@@ -170,140 +784,359 @@ async fn main() -> anyhow::Result<()> {
             println!("Transaction finalized in block {}.", bh);
             println!("The outcome is {:#?}", bs);
         }
-        Action::CreateGame {
+        Action::Upgrade {
             address,
+            module_ref,
+            no_migrate,
         } => {
-            // empty param
-            let message = Parameter::default();
-            let payload = UpdateContractPayload {
-                amount: Amount::zero(),
-                address,
-                receive_name: OwnedReceiveName::new_unchecked("tictactoe.create_game".to_string()),
-                message,
+            println!("Upgrading TIC TAC TOE to {}", module_ref);
+            let params = UpgradeParams {
+                module:  module_ref.bytes,
+                migrate: if no_migrate {
+                    None
+                } else {
+                    Some((OwnedEntrypointName::new_unchecked("migrate".to_string()), 0))
+                },
             };
-
-            let tx = send::update_contract(
-                &keys.account_keys,
-                keys.address,
-                nonce,
-                expiry,
-                payload,
-                1000000u64.into(),
-            );
-            let item = BlockItem::AccountTransaction(tx);
-            // submit the transaction to the chain
-            let transaction_hash = client.send_block_item(&item).await?;
-            println!("Transaction {} submitted (nonce = {}).", transaction_hash, nonce,);
-            let (bh, bs) = client.wait_until_finalized(&transaction_hash).await?;
-            println!("Transaction finalized in block {}.", bh);
-            println!("The outcome is {:#?}", bs);
+            let payload = update_payload(address, "upgrade", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 100000).await?;
         }
-        Action::JoinGame {
-            the_game,
+        Action::Migrate {
             address,
         } => {
-            // the game we want to join.
-            let params = JoinParams {
-                game_id: the_game,
+            println!("Migrating the state of TIC TAC TOE");
+            let payload = update_payload(address, "migrate", Amount::zero(), &());
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000000).await?;
+        }
+        Action::CreateGame {
+            address,
+            stake,
+            settings,
+            draw_seats,
+            tokens,
+        } => {
+            let commitment = if draw_seats {
+                // The commitment is bound to the id the game is going to get.
+                let game_id: u64 = invoke_view(
+                    &mut client,
+                    &consensus_info.last_finalized_block,
+                    address,
+                    "next_game_id",
+                    &(),
+                )
+                .await?;
+                Some(CreatorCommitment {
+                    game_id,
+                    commitment: new_commitment(game_id, keys.address),
+                })
+            } else {
+                None
             };
-
-            let message = Parameter::from(
-                concordium_rust_sdk::types::smart_contracts::concordium_contracts_common::to_bytes(
-                    &params,
-                ),
-            );
-
-            let payload = UpdateContractPayload {
-                amount: Amount::zero(),
-                address,
-                receive_name: OwnedReceiveName::new_unchecked("tictactoe.join_game".to_string()),
-                message,
+            let params = settings.into_params(commitment);
+            let payload = match tokens.token {
+                Some(_) => {
+                    tokens.payload(keys.address, address, &TokenStakeAction::CreateGame(params))?
+                }
+                None => update_payload(address, "create_game", stake, &params),
             };
-
-            let tx = send::update_contract(
-                &keys.account_keys,
-                keys.address,
-                nonce,
-                expiry,
-                payload,
-                1000000u64.into(),
-            );
-            let item = BlockItem::AccountTransaction(tx);
-            // submit the transaction to the chain
-            let transaction_hash = client.send_block_item(&item).await?;
-            println!("Transaction {} submitted (nonce = {}).", transaction_hash, nonce,);
-            let (bh, bs) = client.wait_until_finalized(&transaction_hash).await?;
-            println!("Transaction finalized in block {}.", bh);
-            println!("The outcome is {:#?}", bs);
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
+        }
+        Action::CreateMatch {
+            address,
+            best_of,
+            settings,
+        } => {
+            let params = CreateMatchParams {
+                best_of,
+                settings: settings.into_params(None),
+            };
+            let payload = update_payload(address, "create_match", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
+        }
+        Action::JoinMatch {
+            the_match,
+            address,
+        } => {
+            let params = MatchParams {
+                match_id: the_match,
+            };
+            let payload = update_payload(address, "join_match", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
+        }
+        Action::ViewMatch {
+            the_match,
+            address,
+        } => {
+            let params = MatchParams {
+                match_id: the_match,
+            };
+            let the_match: Match = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "match_view",
+                &params,
+            )
+            .await?;
+            println!("{:#?}", the_match);
+        }
+        Action::CreateTournament {
+            address,
+            size,
+            entry_fee,
+            settings,
+        } => {
+            let params = CreateTournamentParams {
+                size,
+                entry_fee,
+                settings: settings.into_params(None),
+            };
+            let payload = update_payload(address, "create_tournament", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
+        }
+        Action::Register {
+            the_tournament,
+            address,
+            entry_fee,
+        } => {
+            let params = TournamentParams {
+                tournament_id: the_tournament,
+            };
+            let payload = update_payload(address, "register", entry_fee, &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000000).await?;
+        }
+        Action::CancelTournament {
+            the_tournament,
+            address,
+        } => {
+            let params = TournamentParams {
+                tournament_id: the_tournament,
+            };
+            let payload = update_payload(address, "cancel_tournament", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
+        }
+        Action::ViewTournament {
+            the_tournament,
+            address,
+        } => {
+            let params = TournamentParams {
+                tournament_id: the_tournament,
+            };
+            let tournament: Tournament = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "tournament_view",
+                &params,
+            )
+            .await?;
+            println!("{:#?}", tournament);
+        }
+        Action::JoinGame {
+            the_game,
+            address,
+            stake,
+            draw_seats,
+            tokens,
+        } => {
+            // the game we want to join.
+            let params = JoinGameParams {
+                game_id:    the_game,
+                commitment: if draw_seats {
+                    Some(new_commitment(the_game, keys.address))
+                } else {
+                    None
+                },
+            };
+            let payload = match tokens.token {
+                Some(_) => {
+                    tokens.payload(keys.address, address, &TokenStakeAction::JoinGame(params))?
+                }
+                None => update_payload(address, "join_game", stake, &params),
+            };
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
         }
         Action::Move {
             the_game,
             the_move,
             address,
         } => {
-            // the game we want to join.
             let params = MakeMoveParams {
                 game_id: the_game,
                 the_move,
             };
-
-            let message = Parameter::from(
-                concordium_rust_sdk::types::smart_contracts::concordium_contracts_common::to_bytes(
-                    &params,
-                ),
-            );
-
-            let payload = UpdateContractPayload {
-                amount: Amount::zero(),
-                address,
-                receive_name: OwnedReceiveName::new_unchecked("tictactoe.make_move".to_string()),
-                message,
+            let payload = update_payload(address, "make_move", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::Reveal {
+            the_game,
+            secret,
+            address,
+        } => {
+            let params = RevealParams {
+                game_id: the_game,
+                secret:  secret.0,
             };
-
-            let tx = send::update_contract(
-                &keys.account_keys,
-                keys.address,
-                nonce,
-                expiry,
-                payload,
-                10000u64.into(),
-            );
-            let item = BlockItem::AccountTransaction(tx);
-            // submit the transaction to the chain
-            let transaction_hash = client.send_block_item(&item).await?;
-            println!("Transaction {} submitted (nonce = {}).", transaction_hash, nonce,);
-            let (bh, bs) = client.wait_until_finalized(&transaction_hash).await?;
-            println!("Transaction finalized in block {}.", bh);
-            println!("The outcome is {:#?}", bs);
+            let payload = update_payload(address, "reveal", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::ClaimTimeout {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "claim_timeout", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::Resign {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "resign", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::CancelGame {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "cancel_game", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::ProposeAbort {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "propose_abort", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::AcceptAbort {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "accept_abort", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::OfferDraw {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "offer_draw", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::AcceptDraw {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "accept_draw", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::DeclineDraw {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "decline_draw", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::RequestTakeback {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "request_takeback", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::AcceptTakeback {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "accept_takeback", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::Dispute {
+            the_game,
+            address,
+        } => {
+            let params = DisputeParams {
+                game_id: the_game,
+                signed:  None,
+            };
+            let payload = update_payload(address, "dispute", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::SetBot {
+            address,
+            bot,
+        } => {
+            let params = SetBotParams {
+                bot,
+            };
+            let payload = update_payload(address, "set_bot", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::PlayBotTurn {
+            the_game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: the_game,
+            };
+            let payload = update_payload(address, "play_bot_turn", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 1000000).await?;
         }
         Action::ViewState {
             address,
+            start,
+            limit,
+            state,
+            player,
         } => {
-            let message = Parameter::default();
-            let ctx = concordium_rust_sdk::types::smart_contracts::ContractContext {
-                invoker:   None,
-                contract:  address,
-                amount:    Amount::zero(),
-                method:    OwnedReceiveName::new_unchecked("tictactoe.view".to_string()),
-                parameter: message,
-                energy:    10000000u64.into(),
-            };
-
-            match client.invoke_contract(&consensus_info.last_finalized_block, &ctx).await {
-                Ok(res) => {
-                    match res {
-                        concordium_rust_sdk::types::smart_contracts::InvokeContractResult::Success { return_value, events: _, used_energy: _ } => {
-                            if let Some(view_value) = return_value {
-                                let view_state: ViewState = from_bytes(&view_value.value)?;
-                                println!("{:?}",view_state);
-                            }
-                        },
-                        concordium_rust_sdk::types::smart_contracts::InvokeContractResult::Failure { return_value: _, reason, used_energy: _ } => {
-                            eprintln!("Failed invoking contract {:?}", reason);
-                        },
-                    }
-                }
-                Err(err) => eprintln!("Could not invoke contract: {}", err),
+            let params = ListGamesParams {
+                start,
+                limit,
+                state,
+                account: player,
+            };
+            let page: GamePage = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "list_games",
+                &params,
+            )
+            .await?;
+            for game in page.games {
+                println!("{:?}", game);
+            }
+            if let Some(next) = page.next {
+                println!("There are more games, continue with --start {}", next);
             }
         }
         Action::ViewAccounts {
@@ -313,48 +1146,338 @@ async fn main() -> anyhow::Result<()> {
             let params = JoinParams {
                 game_id: the_game,
             };
-
-            let message = Parameter::from(
-                concordium_rust_sdk::types::smart_contracts::concordium_contracts_common::to_bytes(
-                    &params,
-                ),
-            );
-            let ctx = concordium_rust_sdk::types::smart_contracts::ContractContext {
-                invoker:   None,
-                contract:  address,
-                amount:    Amount::zero(),
-                method:    OwnedReceiveName::new_unchecked(
-                    "tictactoe.game_view_players".to_string(),
-                ),
-                parameter: message,
-                energy:    10000000u64.into(),
-            };
-
-            match client.invoke_contract(&consensus_info.last_finalized_block, &ctx).await {
-                Ok(res) => {
-                    match res {
-                        concordium_rust_sdk::types::smart_contracts::InvokeContractResult::Success { return_value, events: _, used_energy: _ } => {
-                            if let Some(view_value) = return_value {
-                                let view_state: Vec<u8> = from_bytes(&view_value.value)?;
-                                println!("{:?}",view_state);
-                            }
-                        },
-                        concordium_rust_sdk::types::smart_contracts::InvokeContractResult::Failure { return_value: _, reason, used_energy: _ } => {
-                            eprintln!("Failed invoking contract {:?}", reason);
-                        },
-                    }
-                }
-                Err(err) => eprintln!("Could not invoke contract: {}", err),
+            let players: Players = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "game_view_players",
+                &params,
+            )
+            .await?;
+            println!("{:?}", players);
+        }
+        Action::History {
+            the_game,
+            address,
+            start,
+            limit,
+        } => {
+            let params = HistoryParams {
+                game_id: the_game,
+                start,
+                limit,
+            };
+            let page: HistoryPage = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "game_history",
+                &params,
+            )
+            .await?;
+            for (number, record) in (start..).zip(page.moves.iter()) {
+                let (mark, account) = match record.player {
+                    Player::Cross(account) => ("cross", account),
+                    Player::Circle(account) => ("circle", account),
+                };
+                let made_at: chrono::DateTime<chrono::Utc> = (std::time::UNIX_EPOCH
+                    + std::time::Duration::from_millis(record.made_at.timestamp_millis()))
+                .into();
+                println!(
+                    "{}. {} ({}) marked cell {} at {}",
+                    number + 1,
+                    mark,
+                    account,
+                    record.cell,
+                    made_at
+                );
+            }
+            if let Some(next) = page.next {
+                println!("There are more moves, continue with --start {}", next);
+            }
+        }
+        Action::Nonce {
+            address,
+            player,
+        } => {
+            let params = AccountParams {
+                account: player.unwrap_or(keys.address),
+            };
+            let nonce: u64 = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "nonce_of",
+                &params,
+            )
+            .await?;
+            println!("{}", nonce);
+        }
+        Action::MyGames {
+            address,
+            player,
+        } => {
+            let params = AccountParams {
+                account: player.unwrap_or(keys.address),
+            };
+            let game_ids: Vec<u64> = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "games_of",
+                &params,
+            )
+            .await?;
+            println!("{:?}", game_ids);
+        }
+        Action::PlayerStats {
+            address,
+            player,
+        } => {
+            let params = AccountParams {
+                account: player.unwrap_or(keys.address),
+            };
+            let stats: PlayerStats = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "player_stats",
+                &params,
+            )
+            .await?;
+            println!("{:#?}", stats);
+        }
+        Action::Leaderboard {
+            address,
+            top,
+        } => {
+            let leaders: Vec<LeaderboardEntry> = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "leaderboard",
+                &LeaderboardParams {
+                    top,
+                },
+            )
+            .await?;
+            for (rank, entry) in leaders.iter().enumerate() {
+                println!(
+                    "{}. {} rating {} ({} wins, {} losses, {} draws)",
+                    rank + 1,
+                    entry.account,
+                    entry.stats.rating,
+                    entry.stats.wins,
+                    entry.stats.losses,
+                    entry.stats.draws
+                );
             }
         }
+        Action::PruneFinished {
+            address,
+            older_than,
+            start,
+        } => {
+            let params = PruneParams {
+                start,
+                older_than,
+            };
+            let payload = update_payload(address, "prune_finished", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000000).await?;
+        }
+        Action::TransferOwnership {
+            address,
+            new_owner,
+        } => {
+            let params = TransferOwnershipParams {
+                new_owner,
+            };
+            let payload = update_payload(address, "transfer_ownership", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::SetAdmin {
+            address,
+            account,
+            revoke,
+        } => {
+            let params = SetAdminParams {
+                account,
+                is_admin: !revoke,
+            };
+            let payload = update_payload(address, "set_admin", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::SetPaused {
+            address,
+            unpause,
+        } => {
+            let params = SetPausedParams {
+                paused: !unpause,
+            };
+            let payload = update_payload(address, "set_paused", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::SetTokenAllowed {
+            address,
+            token,
+            token_id,
+            remove,
+        } => {
+            let params = SetTokenAllowedParams {
+                contract: token,
+                token_id: hex::decode(&token_id).context("The token id must be hex encoded.")?,
+                allowed:  !remove,
+            };
+            let payload = update_payload(address, "set_token_allowed", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::SetMetadataUrl {
+            address,
+            base_url,
+        } => {
+            let params = SetMetadataUrlParams {
+                base_url,
+            };
+            let payload = update_payload(address, "set_metadata_url", Amount::zero(), &params);
+            send_update(&mut client, &keys, nonce, expiry, payload, 10000).await?;
+        }
+        Action::ViewTrophy {
+            game,
+            address,
+        } => {
+            let params = JoinParams {
+                game_id: game,
+            };
+            let trophy: Trophy = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "trophy",
+                &params,
+            )
+            .await?;
+            println!("{:#?}", trophy);
+        }
+        Action::ViewRoles {
+            address,
+        } => {
+            let roles: RolesView = invoke_view(
+                &mut client,
+                &consensus_info.last_finalized_block,
+                address,
+                "roles",
+                &(),
+            )
+            .await?;
+            println!("{:#?}", roles);
+        }
     };
 
     Ok(())
 }
 
-#[derive(Debug, Serialize)]
-pub struct ViewState {
-    pub games: std::collections::BTreeMap<u64, Game>,
+/// A secret committed to for drawing who plays first, given in hex.
+#[derive(Debug)]
+struct Secret([u8; 32]);
+
+impl std::str::FromStr for Secret {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s).context("The secret must be hex encoded.")?;
+        let secret =
+            bytes.try_into().map_err(|_| anyhow::anyhow!("The secret must be 32 bytes."))?;
+        Ok(Secret(secret))
+    }
+}
+
+/// Pick a random secret and return the commitment of 'account' to it in the
+/// game 'game_id'. The secret is printed, as it is needed to reveal it later.
+fn new_commitment(game_id: u64, account: AccountAddress) -> [u8; 32] {
+    let secret: [u8; 32] = rand::random();
+    println!("Keep this secret to reveal it with 'reveal --secret {}'.", hex::encode(secret));
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(b"tictactoe.seat_draw");
+    hasher.update(game_id.to_le_bytes());
+    hasher.update(account.0);
+    hasher.update(secret);
+    hasher.finalize().into()
+}
+
+/// The payload for invoking 'entrypoint' of the tictactoe contract with the
+/// given parameter.
+fn update_payload(
+    address: ContractAddress,
+    entrypoint: &str,
+    amount: Amount,
+    params: &impl Serial,
+) -> UpdateContractPayload {
+    UpdateContractPayload {
+        amount,
+        address,
+        receive_name: OwnedReceiveName::new_unchecked(format!("tictactoe.{}", entrypoint)),
+        message: Parameter::from(to_bytes(params)),
+    }
+}
+
+/// Invoke the view 'entrypoint' of the tictactoe contract and decode what it
+/// returns.
+async fn invoke_view<R: Deserial>(
+    client: &mut endpoints::Client,
+    block: &BlockHash,
+    address: ContractAddress,
+    entrypoint: &str,
+    params: &impl Serial,
+) -> anyhow::Result<R> {
+    let ctx = ContractContext {
+        invoker:   None,
+        contract:  address,
+        amount:    Amount::zero(),
+        method:    OwnedReceiveName::new_unchecked(format!("tictactoe.{}", entrypoint)),
+        parameter: Parameter::from(to_bytes(params)),
+        energy:    10000000u64.into(),
+    };
+    match client.invoke_contract(block, &ctx).await.context("Could not invoke contract.")? {
+        InvokeContractResult::Success {
+            return_value,
+            ..
+        } => {
+            let return_value = return_value.context("The contract did not return a value.")?;
+            Ok(from_bytes(&return_value.value)?)
+        }
+        InvokeContractResult::Failure {
+            reason,
+            ..
+        } => anyhow::bail!("Failed invoking contract {:?}", reason),
+    }
+}
+
+/// Send a contract update signed with the given keys and wait until it is
+/// finalized.
+async fn send_update(
+    client: &mut endpoints::Client,
+    keys: &AccountData,
+    nonce: Nonce,
+    expiry: TransactionTime,
+    payload: UpdateContractPayload,
+    energy: u64,
+) -> anyhow::Result<()> {
+    let tx = send::update_contract(
+        &keys.account_keys,
+        keys.address,
+        nonce,
+        expiry,
+        payload,
+        energy.into(),
+    );
+    let item = BlockItem::AccountTransaction(tx);
+    // submit the transaction to the chain
+    let transaction_hash = client.send_block_item(&item).await?;
+    println!("Transaction {} submitted (nonce = {}).", transaction_hash, nonce,);
+    let (bh, bs) = client.wait_until_finalized(&transaction_hash).await?;
+    println!("Transaction finalized in block {}.", bh);
+    println!("The outcome is {:#?}", bs);
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Copy)]
@@ -363,26 +1486,127 @@ pub enum Player {
     Circle(AccountAddress),
 }
 
+#[derive(Debug, Serialize)]
+pub struct MoveRecord {
+    pub player:  Player,
+    pub cell:    u64,
+    pub made_at: Timestamp,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub moves: Vec<MoveRecord>,
+    pub next:  Option<u32>,
+}
+
 #[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy)]
 pub enum GameState {
     AwaitingOpponent,
     InProgress(Player),
     Finished(Option<Player>), // None if it was a draw, otherwise it contains the winning player.
+    Cancelled,
+    Aborted,
+    AwaitingReveal,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy)]
+pub enum GameStateKind {
+    AwaitingOpponent,
+    InProgress,
+    Finished,
+    Cancelled,
+    Aborted,
+    AwaitingReveal,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
-enum Cell {
-    Empty,
-    Occupied(Player),
+impl std::str::FromStr for GameStateKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "awaiting-opponent" => Ok(GameStateKind::AwaitingOpponent),
+            "in-progress" => Ok(GameStateKind::InProgress),
+            "finished" => Ok(GameStateKind::Finished),
+            "cancelled" => Ok(GameStateKind::Cancelled),
+            "aborted" => Ok(GameStateKind::Aborted),
+            "awaiting-reveal" => Ok(GameStateKind::AwaitingReveal),
+            _ => anyhow::bail!("Unknown game state '{}'.", s),
+        }
+    }
 }
-#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
-pub struct Board([Cell; 9]);
 
-/// A game of tic tac toe!
-#[derive(Debug, PartialEq, Eq, Serialize, Clone)]
-pub struct Game {
+#[derive(Debug, Serialize)]
+pub struct GameListing {
+    pub id:         u64,
     pub game_state: GameState,
-    pub board:      Board,
-    pub cross:      Player,
-    pub circle:     Option<Player>,
+    pub cross:      AccountAddress,
+    pub circle:     Option<AccountAddress>,
+    pub stake:      Amount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GamePage {
+    pub games: Vec<GameListing>,
+    pub next:  Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerStats {
+    pub wins:   u32,
+    pub losses: u32,
+    pub draws:  u32,
+    pub streak: i32,
+    pub rating: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    pub account: AccountAddress,
+    pub stats:   PlayerStats,
+}
+
+#[derive(Debug, Serialize)]
+pub enum MatchState {
+    AwaitingOpponent,
+    InProgress,
+    Finished(Option<AccountAddress>),
+}
+
+#[derive(Debug, Serialize)]
+struct Match {
+    match_state:   MatchState,
+    creator:       AccountAddress,
+    opponent:      Option<AccountAddress>,
+    best_of:       u32,
+    creator_wins:  u32,
+    opponent_wins: u32,
+    games:         Vec<u64>,
+    settings:      CreateGameParams,
+}
+
+#[derive(Debug, Serialize)]
+pub enum TournamentState {
+    Registering,
+    InProgress {
+        round: u32,
+    },
+    Finished {
+        winner:    AccountAddress,
+        runner_up: AccountAddress,
+    },
+    Cancelled,
+}
+
+#[derive(Debug, Serialize)]
+struct Tournament {
+    tournament_state: TournamentState,
+    organizer:        AccountAddress,
+    size:             u32,
+    entry_fee:        Amount,
+    players:          Vec<AccountAddress>,
+    bracket:          Vec<AccountAddress>,
+    current_games:    Vec<u64>,
+    winners:          Vec<Option<AccountAddress>>,
+    games:            Vec<u64>,
+    settings:         CreateGameParams,
 }
@@ -0,0 +1,244 @@
+//! The parameters, return values and events of the CIS-2 token standard, in
+//! the layout wallets expect. The contract mints a trophy to the winner of
+//! every game, with the id of the game as the token id.
+use concordium_std::*;
+
+use crate::GameId;
+
+/// The errors of the CIS-2 entrypoints. The standard fixes the codes of its
+/// own errors, other errors keep the codes of 'R'.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Cis2Error<R> {
+    Custom(R),
+    InvalidTokenId,
+    InsufficientFunds,
+    Unauthorized,
+}
+
+impl<R: Into<Reject>> From<Cis2Error<R>> for Reject {
+    fn from(error: Cis2Error<R>) -> Self {
+        let error_code = match error {
+            Cis2Error::Custom(error) => return error.into(),
+            Cis2Error::InvalidTokenId => -42000001,
+            Cis2Error::InsufficientFunds => -42000002,
+            Cis2Error::Unauthorized => -42000003,
+        };
+        Reject::new(error_code).unwrap_abort()
+    }
+}
+
+impl<R: From<ParseError>> From<ParseError> for Cis2Error<R> {
+    fn from(error: ParseError) -> Self {
+        Cis2Error::Custom(error.into())
+    }
+}
+
+impl<R: From<LogError>> From<LogError> for Cis2Error<R> {
+    fn from(error: LogError) -> Self {
+        Cis2Error::Custom(error.into())
+    }
+}
+
+/// The standards the contract implements, as reported by 'supports'.
+pub const SUPPORTED_STANDARDS: [&str; 2] = ["CIS-0", "CIS-2"];
+
+/// The id of a trophy, which is the id of the game it was won in. It is
+/// serialized as eight little-endian bytes prefixed by their length.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TokenId(pub GameId);
+
+impl Serial for TokenId {
+    fn serial<W: Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        out.write_u8(8)?;
+        self.0.serial(out)
+    }
+}
+
+impl Deserial for TokenId {
+    fn deserial<R: Read>(source: &mut R) -> ParseResult<Self> {
+        if source.read_u8()? != 8 {
+            return Err(ParseError::default());
+        }
+        Ok(TokenId(source.read_u64()?))
+    }
+}
+
+impl schema::SchemaType for TokenId {
+    fn get_type() -> schema::Type {
+        schema::Type::ByteList(schema::SizeLength::U8)
+    }
+}
+
+/// The id of any CIS-2 token, such as the ones games are staked in.
+#[derive(Debug, Serialize, SchemaType, PartialEq, Eq, Clone)]
+#[concordium(transparent)]
+pub struct TokenIdVec(#[concordium(size_length = 1)] pub Vec<u8>);
+
+/// An amount of a token, serialized in unsigned LEB128. Trophies are unique,
+/// so it is either 0 or 1. Amounts of other tokens that do not fit in a u64
+/// are rejected.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TokenAmount(pub u64);
+
+impl Serial for TokenAmount {
+    fn serial<W: Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        let mut value = self.0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return out.write_u8(byte);
+            }
+            out.write_u8(byte | 0x80)?;
+        }
+    }
+}
+
+impl Deserial for TokenAmount {
+    fn deserial<R: Read>(source: &mut R) -> ParseResult<Self> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = source.read_u8()?;
+            let bits = u64::from(byte & 0x7f);
+            // The amounts fit in a u64, anything larger is rejected.
+            if bits << shift >> shift != bits {
+                return Err(ParseError::default());
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(TokenAmount(value));
+            }
+        }
+        Err(ParseError::default())
+    }
+}
+
+impl schema::SchemaType for TokenAmount {
+    fn get_type() -> schema::Type {
+        schema::Type::ULeb128(37)
+    }
+}
+
+/// The receiver of a transfer. Contracts are notified through the given
+/// entrypoint.
+#[derive(Debug, Serialize, SchemaType)]
+pub enum Receiver {
+    Account(AccountAddress),
+    Contract(ContractAddress, OwnedEntrypointName),
+}
+
+impl Receiver {
+    pub fn address(&self) -> Address {
+        match self {
+            Receiver::Account(addr) => Address::Account(*addr),
+            Receiver::Contract(addr, _) => Address::Contract(*addr),
+        }
+    }
+}
+
+/// Data passed on to the receiving contract of a transfer.
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct AdditionalData(#[concordium(size_length = 2)] pub Vec<u8>);
+
+#[derive(Debug, Serialize, SchemaType)]
+pub struct Transfer<T> {
+    pub token_id: T,
+    pub amount: TokenAmount,
+    pub from: Address,
+    pub to: Receiver,
+    pub data: AdditionalData,
+}
+
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct TransferParams<T>(#[concordium(size_length = 2)] pub Vec<Transfer<T>>);
+
+/// The parameter of the 'onReceivingCIS2' entrypoint of a receiving contract.
+#[derive(Debug, Serialize, SchemaType)]
+pub struct OnReceivingCis2Params<T> {
+    pub token_id: T,
+    pub amount: TokenAmount,
+    pub from: Address,
+    pub data: AdditionalData,
+}
+
+#[derive(Debug, Serialize, SchemaType, Clone, Copy, PartialEq, Eq)]
+pub enum OperatorUpdate {
+    Remove,
+    Add,
+}
+
+#[derive(Debug, Serialize, SchemaType)]
+pub struct UpdateOperator {
+    pub update: OperatorUpdate,
+    pub operator: Address,
+}
+
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct UpdateOperatorParams(#[concordium(size_length = 2)] pub Vec<UpdateOperator>);
+
+#[derive(Debug, Serialize, SchemaType)]
+pub struct BalanceOfQuery {
+    pub token_id: TokenId,
+    pub address: Address,
+}
+
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct BalanceOfQueryParams(#[concordium(size_length = 2)] pub Vec<BalanceOfQuery>);
+
+#[derive(Debug, Serialize, SchemaType, PartialEq, Eq)]
+#[concordium(transparent)]
+pub struct BalanceOfQueryResponse(#[concordium(size_length = 2)] pub Vec<TokenAmount>);
+
+#[derive(Debug, Serialize, SchemaType)]
+pub struct OperatorOfQuery {
+    pub owner: Address,
+    pub address: Address,
+}
+
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct OperatorOfQueryParams(#[concordium(size_length = 2)] pub Vec<OperatorOfQuery>);
+
+#[derive(Debug, Serialize, SchemaType, PartialEq, Eq)]
+#[concordium(transparent)]
+pub struct OperatorOfQueryResponse(#[concordium(size_length = 2)] pub Vec<bool>);
+
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct TokenMetadataQueryParams(#[concordium(size_length = 2)] pub Vec<TokenId>);
+
+/// Where the metadata of a token is found, and optionally its SHA-256 hash.
+#[derive(Debug, Serialize, SchemaType, Clone, PartialEq, Eq)]
+pub struct MetadataUrl {
+    #[concordium(size_length = 2)]
+    pub url: String,
+    pub hash: Option<[u8; 32]>,
+}
+
+#[derive(Debug, Serialize, SchemaType, PartialEq, Eq)]
+#[concordium(transparent)]
+pub struct TokenMetadataQueryResponse(#[concordium(size_length = 2)] pub Vec<MetadataUrl>);
+
+/// The name of a standard, like "CIS-2".
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct StandardIdentifier(#[concordium(size_length = 1)] pub String);
+
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(transparent)]
+pub struct SupportsQueryParams(#[concordium(size_length = 2)] pub Vec<StandardIdentifier>);
+
+#[derive(Debug, Serialize, SchemaType, PartialEq, Eq)]
+pub enum SupportResult {
+    NoSupport,
+    Support,
+    SupportBy(#[concordium(size_length = 1)] Vec<ContractAddress>),
+}
+
+#[derive(Debug, Serialize, SchemaType, PartialEq, Eq)]
+#[concordium(transparent)]
+pub struct SupportsQueryResponse(#[concordium(size_length = 2)] pub Vec<SupportResult>);
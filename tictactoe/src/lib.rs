@@ -1,13 +1,34 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-use core::{borrow::BorrowMut, convert::TryInto, ops::Index};
+use core::borrow::BorrowMut;
 
 use concordium_std::*;
 
+mod cis2;
+mod migration;
+use cis2::{
+    AdditionalData, BalanceOfQueryParams, BalanceOfQueryResponse, Cis2Error, MetadataUrl,
+    OnReceivingCis2Params, OperatorOfQueryParams, OperatorOfQueryResponse, OperatorUpdate,
+    Receiver, SupportResult, SupportsQueryParams, SupportsQueryResponse, TokenAmount, TokenId,
+    TokenIdVec, TokenMetadataQueryParams, TokenMetadataQueryResponse, Transfer, TransferParams,
+    UpdateOperator, UpdateOperatorParams, SUPPORTED_STANDARDS,
+};
+use migration::VersionedState;
+
 type GameId = u64;
+type MatchId = u64;
+type TournamentId = u64;
+
+/// The version of the layout of the state. Bump it whenever the state, [Game]
+/// or [Board] change, keep the previous layout in [migration] and convert it
+/// in [VersionedState::migrate].
+const STATE_VERSION: u32 = 1;
 
 #[derive(Serial, DeserialWithState)]
 #[concordium(state_parameter = "S")]
 struct State<S> {
+    // the layout of the state and the games, see [STATE_VERSION]. It comes
+    // first so that any version of the contract can tell the layouts apart.
+    version: u32,
     // we save a counter here as there are
     // no easy way to get the number of games except from
     // iterating over the state.
@@ -15,6 +36,61 @@ struct State<S> {
     // games being played
     // game id - game
     games: StateMap<GameId, Game, S>,
+    // the games each account plays in, so players can find
+    // their own games without scanning all of them.
+    games_by_account: StateMap<AccountAddress, StateSet<GameId, S>, S>,
+    // the moves of the games, kept apart so a move does not rewrite the
+    // whole history of its game
+    // (game id, move number) - move
+    history: StateMap<(GameId, u32), MoveRecord, S>,
+    // results and rating of every account that has finished a game.
+    stats: StateMap<AccountAddress, PlayerStats, S>,
+    // the accounts in 'stats' in the order of the leaderboard, so that it
+    // can be read without going through all of them.
+    rankings: StateSet<RankKey, S>,
+    // the number of matches created, used as the id of the next match.
+    match_ctr: u64,
+    // match series of games between two accounts
+    // match id - match
+    matches: StateMap<MatchId, Match, S>,
+    // the number of tournaments created, used as the id of the next one.
+    tournament_ctr: u64,
+    // single-elimination tournaments
+    // tournament id - tournament
+    tournaments: StateMap<TournamentId, Tournament, S>,
+    // the account that initialized the contract, or the one it transferred
+    // ownership to. The owner manages the admins.
+    owner: AccountAddress,
+    // accounts that may pause the contract besides the owner.
+    admins: StateSet<AccountAddress, S>,
+    // while paused no games can be started or played.
+    paused: bool,
+    // when the contract was last unpaused. The clocks of the running games
+    // start over then, see [Game::deadline].
+    unpaused_at: Timestamp,
+    // bot contracts playing for accounts that opted in
+    // account - bot
+    bots: StateMap<AccountAddress, ContractAddress, S>,
+    // whether a bot is being called, to reject reentrant calls.
+    bot_call_active: bool,
+    // the nonce the next permit of an account has to carry
+    // account - nonce
+    nonces: StateMap<AccountAddress, u64, S>,
+    // compact records of finished games removed from 'games'
+    // game id - summary
+    archive: StateMap<GameId, GameSummary, S>,
+    // the CIS-2 trophies minted to the winners of games
+    // game id - trophy
+    trophies: StateMap<GameId, Trophy, S>,
+    // the URL the metadata of a trophy is found at once its token id is
+    // appended in hex, see [State::trophy_metadata_url].
+    metadata_base_url: String,
+    // the operators allowed to transfer the trophies of an owner
+    // (owner, operator)
+    operators: StateSet<(Address, Address), S>,
+    // the tokens games can be staked in, managed by the admins
+    // (token contract, token id)
+    allowed_tokens: StateSet<(ContractAddress, TokenIdVec), S>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Copy, SchemaType)]
@@ -24,14 +100,23 @@ pub enum Player {
 }
 
 impl Player {
-    fn to_cell(&self) -> Cell {
-        Cell::Occupied(*self)
+    fn to_cell(self) -> Cell {
+        Cell::from(&self)
+    }
+
+    fn address(&self) -> AccountAddress {
+        match self {
+            Player::Cross(addr) | Player::Circle(addr) => *addr,
+        }
     }
 }
 
 impl From<&Player> for Cell {
     fn from(p: &Player) -> Self {
-        Cell::Occupied(*p)
+        match p {
+            Player::Cross(_) => Cell::Cross,
+            Player::Circle(_) => Cell::Circle,
+        }
     }
 }
 
@@ -40,29 +125,366 @@ pub enum GameState {
     AwaitingOpponent,
     InProgress(Player),
     Finished(Option<Player>), // None if it was a draw, otherwise it contains the winning player.
+    Cancelled,                // The creator withdrew the game before anyone joined.
+    Aborted,                  // Both players agreed to stop the game.
+    AwaitingReveal,           // Both players committed and have to reveal who plays first.
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, SchemaType)]
+/// The kind of a [GameState] without the players, used for filtering games.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy, SchemaType)]
+pub enum GameStateKind {
+    AwaitingOpponent,
+    InProgress,
+    Finished,
+    Cancelled,
+    Aborted,
+    AwaitingReveal,
+}
+
+impl GameState {
+    fn kind(&self) -> GameStateKind {
+        match self {
+            GameState::AwaitingOpponent => GameStateKind::AwaitingOpponent,
+            GameState::InProgress(_) => GameStateKind::InProgress,
+            GameState::Finished(_) => GameStateKind::Finished,
+            GameState::Cancelled => GameStateKind::Cancelled,
+            GameState::Aborted => GameStateKind::Aborted,
+            GameState::AwaitingReveal => GameStateKind::AwaitingReveal,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum Cell {
     Empty,
-    Occupied(Player),
+    Cross,
+    Circle,
+}
+
+impl Cell {
+    fn to_bits(self) -> u8 {
+        match self {
+            Cell::Empty => 0,
+            Cell::Cross => 1,
+            Cell::Circle => 2,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => Cell::Cross,
+            2 => Cell::Circle,
+            _ => Cell::Empty,
+        }
+    }
 }
 
+/// The smallest number of rows and columns of a board.
+const MIN_BOARD_SIZE: u8 = 3;
+/// The largest number of rows and columns of a board.
+const MAX_BOARD_SIZE: u8 = 19;
+
+/// A square board of 'size' × 'size' cells, numbered row by row from the
+/// top left corner.
 #[derive(Debug, Serialize, PartialEq, Eq, Clone, SchemaType)]
-pub struct Board([Cell; 9]);
+pub struct Board {
+    /// The number of rows and columns.
+    pub size: u8,
+    /// The number of marks in a row, column or diagonal needed to win.
+    pub win_length: u8,
+    /// The cells packed four to a byte, two bits each: 0 when empty, 1 for
+    /// cross and 2 for circle. This keeps large boards small in the state.
+    cells: Vec<u8>,
+}
 
 impl Board {
-    fn new() -> Self {
-        Board(vec![Cell::Empty; 9].try_into().unwrap())
+    fn new(size: u8, win_length: u8) -> Self {
+        let cell_count = usize::from(size) * usize::from(size);
+        Board {
+            size,
+            win_length,
+            cells: vec![0; cell_count.div_ceil(4)],
+        }
+    }
+
+    fn cell_count(&self) -> usize {
+        usize::from(self.size) * usize::from(self.size)
+    }
+
+    /// The content of the cell 'index', or None if it is not on the board.
+    fn get(&self, index: usize) -> Option<Cell> {
+        if index >= self.cell_count() {
+            return None;
+        }
+        Some(Cell::from_bits(
+            (self.cells[index / 4] >> (2 * (index % 4))) & 0b11,
+        ))
+    }
+
+    fn set(&mut self, index: usize, cell: Cell) {
+        let shift = 2 * (index % 4);
+        let byte = &mut self.cells[index / 4];
+        *byte = (*byte & !(0b11 << shift)) | (cell.to_bits() << shift);
+    }
+
+    /// The number of cells that have been marked.
+    fn mark_count(&self) -> u32 {
+        (0..self.cell_count())
+            .filter(|index| self.get(*index) != Some(Cell::Empty))
+            .count() as u32
+    }
+
+    fn is_full(&self) -> bool {
+        (0..self.cell_count()).all(|index| self.get(index) != Some(Cell::Empty))
+    }
+
+    /// Whether the mark in the cell 'index' is part of 'win_length' equal
+    /// marks in a row, column or diagonal. Only the lines through the cell
+    /// are checked, so this should be called after every move.
+    fn is_winning(&self, index: usize) -> bool {
+        let cell = match self.get(index) {
+            Some(Cell::Empty) | None => return false,
+            Some(cell) => cell,
+        };
+        let size = i32::from(self.size);
+        let (row, column) = (index as i32 / size, index as i32 % size);
+        [(0, 1), (1, 0), (1, 1), (1, -1)].iter().any(|&(dr, dc)| {
+            let line = 1
+                + self.count_marks(cell, row, column, dr, dc)
+                + self.count_marks(cell, row, column, -dr, -dc);
+            line >= u32::from(self.win_length)
+        })
+    }
+
+    /// The number of 'cell' marks next to each other, starting next to
+    /// ('row', 'column') and going in the direction ('dr', 'dc').
+    fn count_marks(&self, cell: Cell, row: i32, column: i32, dr: i32, dc: i32) -> u32 {
+        let size = i32::from(self.size);
+        let (mut r, mut c) = (row + dr, column + dc);
+        let mut count = 0;
+        while (0..size).contains(&r)
+            && (0..size).contains(&c)
+            && self.get((r * size + c) as usize) == Some(cell)
+        {
+            count += 1;
+            r += dr;
+            c += dc;
+        }
+        count
     }
 }
 
 impl Default for Board {
+    /// The classic 3×3 board with three in a row.
     fn default() -> Self {
-        Self::new()
+        Self::new(3, 3)
+    }
+}
+
+/// A SHA-256 hash committing to a [Secret], see [SEAT_DRAW_PREFIX].
+type Commitment = [u8; 32];
+/// A random value picked by a player to decide who plays first.
+type Secret = [u8; 32];
+
+/// The prefix of what a player commits to when drawing seats. The commitment
+/// is the SHA-256 hash of the prefix followed by the id of the game as eight
+/// little-endian bytes, the account of the player and the secret. This binds
+/// it to one player in one game, so the opponent cannot copy it, and neither
+/// can it be replayed in another game.
+const SEAT_DRAW_PREFIX: &[u8] = b"tictactoe.seat_draw";
+
+/// The commitment of the creator of a game. The game does not exist yet, so
+/// the creator binds the commitment to the id the game is going to get.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, SchemaType)]
+pub struct CreatorCommitment {
+    /// The id of the game, as returned by 'next_game_id'. Creating the game
+    /// fails if it would get another id.
+    pub game_id: GameId,
+    pub commitment: Commitment,
+}
+
+/// The commitments and secrets that decide at random who of the creator and
+/// the joiner plays cross and so moves first. Each player commits to a secret
+/// when creating or joining the game and reveals it afterwards, so neither
+/// player can pick their secret knowing the other one.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct SeatDraw {
+    pub creator_commitment: Commitment,
+    pub joiner_commitment: Option<Commitment>,
+    pub creator_secret: Option<Secret>,
+    pub joiner_secret: Option<Secret>,
+}
+
+impl SeatDraw {
+    /// Whether the joiner plays cross, i.e. whether the XOR of the secrets
+    /// has an odd number of bits set, so that every bit of both secrets
+    /// counts. None until both secrets are revealed.
+    fn joiner_starts(&self) -> Option<bool> {
+        match (self.creator_secret, self.joiner_secret) {
+            (Some(creator), Some(joiner)) => {
+                let bits: u32 = creator
+                    .iter()
+                    .zip(joiner.iter())
+                    .map(|(c, j)| (c ^ j).count_ones())
+                    .sum();
+                Some(bits % 2 == 1)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The rules a game is played by.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy, SchemaType)]
+pub enum GameVariant {
+    /// A single board.
+    Classic,
+    /// Nine 3×3 sub-boards laid out in a 3×3 grid. The cell a player marks
+    /// decides the sub-board the opponent has to play in next, and winning
+    /// three sub-boards in a row wins the game.
+    Ultimate,
+}
+
+/// The sub-boards of an ultimate game. The [Game::board] of the game is the
+/// meta board, where a cell is marked when its sub-board is won.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct UltimateBoard {
+    /// The nine sub-boards, row by row.
+    pub sub_boards: Vec<Board>,
+    /// The sub-board the next move has to be in, or None if the player in
+    /// turn can pick any sub-board that is still open.
+    pub next_sub_board: Option<u8>,
+}
+
+impl UltimateBoard {
+    fn new() -> Self {
+        UltimateBoard {
+            sub_boards: vec![Board::default(); 9],
+            next_sub_board: None,
+        }
+    }
+
+    /// Whether moves can still be made in the sub-board 'sub', i.e. it is
+    /// neither won nor full.
+    fn is_open(&self, meta: &Board, sub: usize) -> bool {
+        meta.get(sub) == Some(Cell::Empty) && !self.sub_boards[sub].is_full()
+    }
+
+    /// Undo 'the_move', the last move of the game, where 'previous' is the
+    /// move before it.
+    fn undo(&mut self, meta: &mut Board, the_move: usize, previous: Option<usize>) {
+        let (sub, cell) = (the_move / 9, the_move % 9);
+        self.sub_boards[sub].set(cell, Cell::Empty);
+        // The sub-board was open before the move, so if it is marked on the
+        // meta board the move won it.
+        meta.set(sub, Cell::Empty);
+        self.next_sub_board = previous
+            .map(|previous| previous % 9)
+            .filter(|next| self.is_open(meta, *next))
+            .map(|next| next as u8);
+    }
+
+    /// Put the mark of 'player' in the cell 'the_move % 9' of the sub-board
+    /// 'the_move / 9', marking the meta board if that wins the sub-board.
+    /// Returns whether the game is finished and the winner, like
+    /// [Game::is_game_finished].
+    fn make_move(
+        &mut self,
+        meta: &mut Board,
+        player: &Player,
+        the_move: &PutMove,
+    ) -> ContractResult<(bool, Option<Player>)> {
+        let (sub, cell) = (the_move.0 / 9, the_move.0 % 9);
+        ensure!(
+            sub < 9 && self.is_open(meta, sub),
+            CustomContractError::InvalidMove
+        );
+        if let Some(next) = self.next_sub_board {
+            ensure!(usize::from(next) == sub, CustomContractError::WrongSubBoard);
+        }
+        ensure!(
+            self.sub_boards[sub].get(cell) == Some(Cell::Empty),
+            CustomContractError::InvalidMove
+        );
+        self.sub_boards[sub].set(cell, player.to_cell());
+        if self.sub_boards[sub].is_winning(cell) {
+            meta.set(sub, player.to_cell());
+            if meta.is_winning(sub) {
+                return Ok((true, Some(*player)));
+            }
+        }
+        // The opponent is sent to the sub-board matching the cell, unless
+        // it is closed already.
+        self.next_sub_board = if self.is_open(meta, cell) {
+            Some(cell as u8)
+        } else {
+            None
+        };
+        if (0..9).all(|sub| !self.is_open(meta, sub)) {
+            return Ok((true, None));
+        }
+        Ok((false, None))
     }
 }
 
+/// A move as recorded in the history of a game.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct MoveRecord {
+    pub player: Player,
+    /// The cell marked, numbered as in 'make_move'.
+    pub cell: u64,
+    pub made_at: Timestamp,
+}
+
+/// The trophy minted to the winner of a game. It is a CIS-2 token whose id is
+/// the id of the game. Its metadata is served off-chain, see
+/// [State::trophy_metadata_url], from what the 'trophy' view returns.
+#[derive(Debug, Serialize, Clone, SchemaType)]
+pub struct Trophy {
+    /// The account or contract holding the trophy.
+    pub owner: Address,
+    /// The account that lost the game.
+    pub opponent: AccountAddress,
+    /// The board at the end of the game, the meta board for ultimate games.
+    pub board: Board,
+}
+
+/// A game played off-chain. The players exchange [ChannelState]s signed by
+/// both of them, and only the final one is submitted with 'close_channel'.
+/// When a player stops cooperating, the other one submits the last state they
+/// both signed with 'dispute', and the game continues on-chain with the usual
+/// rules once the challenge window has passed. Until then, a newer state
+/// signed by both can replace the disputed one.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct Channel {
+    /// The number of moves on the board: those of the last state submitted
+    /// on-chain, and those made on-chain since.
+    pub move_count: u32,
+    /// The end of the challenge window, once the channel is disputed.
+    pub challenge_end: Option<Timestamp>,
+}
+
+/// A position of a game played in a channel, which both players sign
+/// off-chain.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct ChannelState {
+    /// The contract the game is played in.
+    pub contract_address: ContractAddress,
+    pub game_id: GameId,
+    /// The number of moves made to reach the position.
+    pub move_count: u32,
+    pub board: Board,
+}
+
+/// A stake in CIS-2 tokens, received through 'onReceivingCIS2'.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct TokenStake {
+    /// The token contract, which is paid out through its 'transfer'.
+    pub contract: ContractAddress,
+    pub token_id: TokenIdVec,
+    pub amount: TokenAmount,
+}
+
 /// A game of tic tac toe!
 #[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
 pub struct Game {
@@ -70,8 +492,59 @@ pub struct Game {
     pub board: Board,
     pub cross: Player,
     pub circle: Option<Player>,
+    /// The amount of CCD each player has put into the pot.
+    pub stake: Amount,
+    /// When the last move was made, or when the game started. Once the game
+    /// is over, this is when it ended.
+    pub last_move: Timestamp,
+    /// How long a player has to make a move before the opponent can claim
+    /// the win.
+    pub move_timeout: Duration,
+    /// The player who proposed to abort the game, if any.
+    pub abort_proposal: Option<Player>,
+    /// The only account allowed to join, if the game is private.
+    pub invited: Option<AccountAddress>,
+    /// The sub-boards, if this is an ultimate game.
+    pub ultimate: Option<UltimateBoard>,
+    /// The draw deciding who plays first, if the creator committed to a
+    /// secret. Otherwise the creator plays cross.
+    pub seat_draw: Option<SeatDraw>,
+    /// The match this game is part of, if any.
+    pub match_id: Option<MatchId>,
+    /// The tournament this game is part of, if any.
+    pub tournament_id: Option<TournamentId>,
+    /// The number of moves in the history of the game. The moves themselves
+    /// are stored in the contract state, numbered from 0.
+    pub history_len: u32,
+    /// The player who offered a draw, if any. The offer expires with the
+    /// next move.
+    pub draw_offer: Option<Player>,
+    /// The player who asked to take back their last move, if any. The
+    /// request expires with the next move.
+    pub takeback_request: Option<Player>,
+    /// The channel the moves are played in, if they are played off-chain.
+    pub channel: Option<Channel>,
+    /// The CIS-2 tokens each player has put into the pot, if the game is
+    /// staked in tokens rather than CCD.
+    pub token_stake: Option<TokenStake>,
 }
 
+/// The version of the layout of the compact view returned by 'game_view',
+/// kept in its top four bits. The layout below is version 0, so views look
+/// the same as before the version was added. Bump it whenever the layout
+/// changes, so that readers can tell views they do not understand.
+const COMPACT_GAME_VERSION: u32 = 0;
+
+/// The compact view of a game used by 'game_view'. It only fits the classic
+/// 3×3 board, other boards are available through 'game_board'. From the least
+/// significant bit:
+/// - bits 0-3: the state of the game. 0 awaiting the opponent, 1 cross to
+///   move, 2 circle to move, 3 drawn, 4 won by cross, 5 won by circle,
+///   6 cancelled, 7 aborted and 8 awaiting the reveals.
+/// - bits 4-21: the nine cells row by row from the top left, two bits each.
+///   0 is empty, 1 cross and 2 circle.
+/// - bits 22-27: unused, always 0.
+/// - bits 28-31: [COMPACT_GAME_VERSION].
 impl From<Game> for u32 {
     fn from(g: Game) -> Self {
         let mut state_bits = match g.game_state {
@@ -81,27 +554,55 @@ impl From<Game> for u32 {
             GameState::Finished(None) => 0x00000003,
             GameState::Finished(Some(Player::Cross(_))) => 0x00000004,
             GameState::Finished(Some(Player::Circle(_))) => 0x00000005,
+            GameState::Cancelled => 0x00000006,
+            GameState::Aborted => 0x00000007,
+            GameState::AwaitingReveal => 0x00000008,
         };
-        for (index, cell) in g.board.0.iter().enumerate() {
-            let cell_bits = match cell {
-                Cell::Empty => 0,
-                Cell::Occupied(Player::Cross(_)) => 1,
-                Cell::Occupied(Player::Circle(_)) => 2,
-            };
-            state_bits |= cell_bits << (4 + 2 * index);
+        for index in 0..9 {
+            let cell_bits = g.board.get(index).map_or(0, Cell::to_bits);
+            state_bits |= u32::from(cell_bits) << (4 + 2 * index);
         }
-        state_bits
+        state_bits | COMPACT_GAME_VERSION << 28
     }
 }
 
 impl Game {
-    /// Create a new game with an initiator.
-    fn new(initiator: AccountAddress) -> Self {
+    /// Create a new game with an initiator staking 'stake'.
+    fn new(initiator: AccountAddress, stake: Amount, params: &CreateGameParams) -> Self {
         Game {
             game_state: GameState::AwaitingOpponent,
-            board: Board::new(),
+            board: Board::new(params.board_size, params.win_length),
             cross: Player::Cross(initiator),
             circle: None,
+            stake,
+            last_move: Timestamp::from_timestamp_millis(0),
+            move_timeout: params.move_timeout,
+            abort_proposal: None,
+            invited: params.opponent,
+            ultimate: match params.variant {
+                GameVariant::Classic => None,
+                GameVariant::Ultimate => Some(UltimateBoard::new()),
+            },
+            seat_draw: params.commitment.map(|creator| SeatDraw {
+                creator_commitment: creator.commitment,
+                joiner_commitment: None,
+                creator_secret: None,
+                joiner_secret: None,
+            }),
+            match_id: None,
+            tournament_id: None,
+            history_len: 0,
+            draw_offer: None,
+            takeback_request: None,
+            channel: if params.channel {
+                Some(Channel {
+                    move_count: 0,
+                    challenge_end: None,
+                })
+            } else {
+                None
+            },
+            token_stake: None,
         }
     }
 
@@ -113,52 +614,513 @@ impl Game {
         );
         // We don't allow people to play against themself.
         ensure!(self.cross != new_player, CustomContractError::InvalidJoin);
+        // Private games can only be joined by the invited player.
+        if let Some(invited) = self.invited {
+            ensure!(
+                new_player.address() == invited,
+                CustomContractError::NotInvited
+            );
+        }
         // Let the player join and set it in progress.
-        // Game initiator (cross) starts!
+        // Game initiator (cross) starts, unless the seats are drawn first.
         self.circle = Some(new_player);
-        self.game_state = GameState::InProgress(self.cross);
+        self.game_state = if self.seat_draw.is_some() {
+            GameState::AwaitingReveal
+        } else {
+            GameState::InProgress(self.cross)
+        };
+        Ok(())
+    }
+
+    /// Reveal the secret of 'addr', where 'hash' is the commitment to
+    /// 'secret' described in [SEAT_DRAW_PREFIX]. Once both secrets are revealed the seats are drawn, and the
+    /// game starts with cross to move.
+    fn reveal(
+        &mut self,
+        addr: &AccountAddress,
+        secret: Secret,
+        hash: Commitment,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        ensure!(
+            self.game_state == GameState::AwaitingReveal,
+            CustomContractError::InvalidGameState
+        );
+        // Until the seats are drawn the creator sits as cross.
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        let draw = self
+            .seat_draw
+            .as_mut()
+            .ok_or(CustomContractError::InvalidGameState)?;
+        let (commitment, revealed) = match player {
+            Player::Cross(_) => (Some(draw.creator_commitment), &mut draw.creator_secret),
+            Player::Circle(_) => (draw.joiner_commitment, &mut draw.joiner_secret),
+        };
+        ensure!(revealed.is_none(), CustomContractError::AlreadyRevealed);
+        ensure!(commitment == Some(hash), CustomContractError::WrongSecret);
+        *revealed = Some(secret);
+
+        if let Some(joiner_starts) = draw.joiner_starts() {
+            if let (true, Some(joiner)) = (joiner_starts, self.circle) {
+                let creator = self.cross.address();
+                self.cross = Player::Cross(joiner.address());
+                self.circle = Some(Player::Circle(creator));
+            }
+            self.game_state = GameState::InProgress(self.cross);
+            // The clock for the first move starts now.
+            self.last_move = now;
+        }
         Ok(())
     }
 
+    /// The player who revealed their secret while the opponent has not.
+    fn revealed_alone(&self) -> Option<Player> {
+        let draw = self.seat_draw.as_ref()?;
+        match (draw.creator_secret, draw.joiner_secret) {
+            (Some(_), None) => Some(self.cross),
+            (None, Some(_)) => self.circle,
+            _ => None,
+        }
+    }
+
     fn make_move(&mut self, player: &Player, the_move: PutMove) -> ContractResult<()> {
         // A player can only make a move if its their turn.
         ensure!(
             Self::is_it_me(self.game_state, player),
             CustomContractError::NotMyTurn
         );
-        // A player can only make valid move.
-        ensure!(
-            Self::is_valid_move(self, &the_move),
-            CustomContractError::InvalidMove
-        );
-
-        // Update the board.
-        // This is hideous - we can make it better.
-        self.borrow_mut().board.0[the_move.0] = player.to_cell();
+        let finished = match self.ultimate.as_mut() {
+            // In an ultimate game the move goes into a sub-board.
+            Some(ultimate) => ultimate.make_move(&mut self.board, player, &the_move)?,
+            None => {
+                // A player can only make valid move.
+                ensure!(
+                    Self::is_valid_move(self, &the_move),
+                    CustomContractError::InvalidMove
+                );
+                // Update the board.
+                self.board.set(the_move.0, player.to_cell());
+                self.is_game_finished(player, &the_move)
+            }
+        };
+        // Making a move turns down a pending proposal to abort, and any
+        // pending offer of a draw or request to take back a move.
+        self.abort_proposal = None;
+        self.draw_offer = None;
+        self.takeback_request = None;
 
         // If the game is not yet finished we let the other player
         // make their move otherwise we mark the game as finished with the outcome.
-        if let (true, result) = self.is_game_finished(player, &the_move) {
+        if let (true, result) = finished {
             self.borrow_mut().game_state = GameState::Finished(result)
         } else {
             self.borrow_mut().game_state = match player {
-                Player::Cross(_) => GameState::InProgress(self.circle.unwrap()), // should be safeish
+                Player::Cross(_) => {
+                    GameState::InProgress(self.circle.ok_or(CustomContractError::InvalidGameState)?)
+                }
                 Player::Circle(_) => GameState::InProgress(self.cross),
             }
         }
         Ok(())
     }
 
+    /// The point in time after which the player in turn can no longer move.
+    /// Moves are blocked while the contract is paused, so the clock starts
+    /// over when it is unpaused at 'unpaused_at'.
+    fn deadline(&self, unpaused_at: Timestamp) -> Timestamp {
+        self.last_move
+            .max(unpaused_at)
+            .checked_add(self.move_timeout)
+            .unwrap_or_else(|| Timestamp::from_timestamp_millis(u64::MAX))
+    }
+
+    /// Let the player who is waiting for the opponent win the game,
+    /// if the opponent did not move before the deadline.
+    fn claim_timeout(
+        &mut self,
+        claimant: &AccountAddress,
+        now: Timestamp,
+        unpaused_at: Timestamp,
+    ) -> ContractResult<()> {
+        let waiting = match self.game_state {
+            GameState::InProgress(Player::Cross(_)) => self.circle,
+            GameState::InProgress(Player::Circle(_)) => Some(self.cross),
+            // A player who does not reveal their secret in time loses.
+            GameState::AwaitingReveal => Some(
+                self.revealed_alone()
+                    .ok_or(CustomContractError::InvalidTimeoutClaim)?,
+            ),
+            _ => None,
+        }
+        .ok_or(CustomContractError::InvalidGameState)?;
+        ensure!(
+            waiting.address() == *claimant,
+            CustomContractError::InvalidTimeoutClaim
+        );
+        ensure!(
+            now > self.deadline(unpaused_at),
+            CustomContractError::DeadlineNotPassed
+        );
+        self.check_on_chain_play(now)?;
+        self.end(GameState::Finished(Some(waiting)));
+        Ok(())
+    }
+
+    /// End the game in 'game_state'. Pending proposals, offers and requests
+    /// end with it, so that none of them can be accepted afterwards.
+    fn end(&mut self, game_state: GameState) {
+        self.game_state = game_state;
+        self.abort_proposal = None;
+        self.draw_offer = None;
+        self.takeback_request = None;
+    }
+
+    /// The seat taken by 'addr' in this game, if any.
+    fn player(&self, addr: &AccountAddress) -> Option<Player> {
+        if self.cross.address() == *addr {
+            Some(self.cross)
+        } else {
+            self.circle.filter(|circle| circle.address() == *addr)
+        }
+    }
+
+    /// The opponent of 'player', if anyone has joined.
+    fn opponent(&self, player: &Player) -> Option<Player> {
+        match player {
+            Player::Cross(_) => self.circle,
+            Player::Circle(_) => Some(self.cross),
+        }
+    }
+
+    /// Whether both players have joined and the game is not over yet.
+    fn is_running(&self) -> bool {
+        matches!(
+            self.game_state,
+            GameState::InProgress(_) | GameState::AwaitingReveal
+        )
+    }
+
+    /// Give up a game in progress, letting the opponent win.
+    fn resign(&mut self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(self.is_running(), CustomContractError::CannotResign);
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        self.end(GameState::Finished(self.opponent(&player)));
+        Ok(())
+    }
+
+    /// Withdraw a game nobody has joined yet.
+    fn cancel(&mut self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(
+            self.game_state == GameState::AwaitingOpponent,
+            CustomContractError::CannotCancel
+        );
+        ensure!(
+            self.cross.address() == *addr,
+            CustomContractError::NotTheCreator
+        );
+        self.game_state = GameState::Cancelled;
+        Ok(())
+    }
+
+    /// Propose to the opponent to stop the game without a result.
+    fn propose_abort(&mut self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(self.is_running(), CustomContractError::CannotAbort);
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        ensure!(
+            self.abort_proposal.is_none(),
+            CustomContractError::AbortAlreadyProposed
+        );
+        self.abort_proposal = Some(player);
+        Ok(())
+    }
+
+    /// Accept the abort proposed by the opponent.
+    fn accept_abort(&mut self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(self.is_running(), CustomContractError::CannotAbort);
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        match self.abort_proposal {
+            Some(proposer) if proposer != player => {
+                self.end(GameState::Aborted);
+                Ok(())
+            }
+            _ => Err(CustomContractError::NoAbortProposal),
+        }
+    }
+
+    /// Offer the opponent to end the game in a draw.
+    fn offer_draw(&mut self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(
+            matches!(self.game_state, GameState::InProgress(_)),
+            CustomContractError::InvalidGameState
+        );
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        ensure!(
+            self.draw_offer.is_none(),
+            CustomContractError::OfferAlreadyMade
+        );
+        self.draw_offer = Some(player);
+        Ok(())
+    }
+
+    /// Accept the draw offered by the opponent, or decline it if 'accept' is
+    /// false.
+    fn answer_draw(&mut self, addr: &AccountAddress, accept: bool) -> ContractResult<()> {
+        ensure!(
+            matches!(self.game_state, GameState::InProgress(_)),
+            CustomContractError::InvalidGameState
+        );
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        match self.draw_offer {
+            Some(offerer) if offerer != player => {
+                self.draw_offer = None;
+                if accept {
+                    self.end(GameState::Finished(None));
+                }
+                Ok(())
+            }
+            _ => Err(CustomContractError::NoPendingOffer),
+        }
+    }
+
+    /// Ask the opponent to let the player take back the move they just made,
+    /// which is 'last' in the history.
+    fn request_takeback(
+        &mut self,
+        addr: &AccountAddress,
+        last: Option<&MoveRecord>,
+    ) -> ContractResult<()> {
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        ensure!(
+            matches!(self.game_state, GameState::InProgress(_))
+                && last.map(|last| last.player) == Some(player),
+            CustomContractError::CannotTakeBack
+        );
+        ensure!(
+            self.takeback_request.is_none(),
+            CustomContractError::OfferAlreadyMade
+        );
+        self.takeback_request = Some(player);
+        Ok(())
+    }
+
+    /// Accept the request of the opponent to take back their last move, which
+    /// follows 'previous' in the history. The move is removed from the board
+    /// and it is the turn of the opponent again, with a fresh deadline. The
+    /// caller removes it from the history. Returns the move taken back.
+    fn accept_takeback(
+        &mut self,
+        addr: &AccountAddress,
+        last: Option<&MoveRecord>,
+        previous: Option<&MoveRecord>,
+        now: Timestamp,
+    ) -> ContractResult<MoveRecord> {
+        ensure!(
+            matches!(self.game_state, GameState::InProgress(_)),
+            CustomContractError::CannotTakeBack
+        );
+        let player = self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        let requester = match self.takeback_request {
+            Some(requester) if requester != player => requester,
+            _ => return Err(CustomContractError::NoPendingOffer),
+        };
+        let last = last.cloned().ok_or(CustomContractError::CannotTakeBack)?;
+        match self.ultimate.as_mut() {
+            Some(ultimate) => {
+                let previous = previous.map(|previous| previous.cell as usize);
+                ultimate.undo(&mut self.board, last.cell as usize, previous)
+            }
+            None => self.board.set(last.cell as usize, Cell::Empty),
+        }
+        self.history_len -= 1;
+        if let Some(channel) = self.channel.as_mut() {
+            channel.move_count -= 1;
+        }
+        self.takeback_request = None;
+        self.draw_offer = None;
+        self.game_state = GameState::InProgress(requester);
+        self.last_move = now;
+        Ok(last)
+    }
+
+    /// Whether the moves can be played on-chain at 'now'. In a channel they
+    /// can only once it is disputed and the challenge window has passed.
+    fn check_on_chain_play(&self, now: Timestamp) -> ContractResult<()> {
+        match self.channel.as_ref().map(|channel| channel.challenge_end) {
+            None => Ok(()),
+            Some(None) => Err(CustomContractError::ChannelOpen),
+            Some(Some(challenge_end)) => {
+                ensure!(
+                    now > challenge_end,
+                    CustomContractError::ChallengeWindowOpen
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Check that 'state' is a position of this channel game reached by
+    /// alternate moves, starting with cross, from the position on-chain, and
+    /// return whether it ends the game and the winner, like
+    /// [Game::is_game_finished].
+    fn check_channel_state(&self, state: &ChannelState) -> ContractResult<(bool, Option<Player>)> {
+        let channel = self
+            .channel
+            .as_ref()
+            .ok_or(CustomContractError::NotAChannel)?;
+        ensure!(
+            matches!(self.game_state, GameState::InProgress(_)),
+            CustomContractError::InvalidGameState
+        );
+        ensure!(
+            state.move_count >= channel.move_count,
+            CustomContractError::StaleChannelState
+        );
+        let board = &state.board;
+        ensure!(
+            board.size == self.board.size
+                && board.win_length == self.board.win_length
+                && board.cells.len() == self.board.cells.len(),
+            CustomContractError::InvalidChannelState
+        );
+        // The marks on-chain cannot be taken back off-chain.
+        ensure!(
+            (0..self.board.cell_count()).all(
+                |i| self.board.get(i) == Some(Cell::Empty) || board.get(i) == self.board.get(i)
+            ),
+            CustomContractError::InvalidChannelState
+        );
+        let count = |mark| {
+            (0..board.cell_count())
+                .filter(|i| board.get(*i) == Some(mark))
+                .count()
+        };
+        let (crosses, circles) = (count(Cell::Cross), count(Cell::Circle));
+        ensure!(
+            (crosses == circles || crosses == circles + 1)
+                && crosses + circles == state.move_count as usize,
+            CustomContractError::InvalidChannelState
+        );
+        let circle = self.circle.ok_or(CustomContractError::InvalidGameState)?;
+        let mut winners = (0..board.cell_count())
+            .filter(|i| board.get(*i) != Some(Cell::Empty) && board.is_winning(*i))
+            .map(|i| {
+                if board.get(i) == Some(Cell::Cross) {
+                    self.cross
+                } else {
+                    circle
+                }
+            });
+        let winner = winners.next();
+        ensure!(
+            winners.all(|other| Some(other) == winner),
+            CustomContractError::InvalidChannelState
+        );
+        Ok((winner.is_some() || board.is_full(), winner))
+    }
+
+    /// Put the position 'state' on the board, ending the game if it is over.
+    /// Like a move, this turns down anything pending.
+    fn apply_channel_state(&mut self, state: ChannelState, finished: (bool, Option<Player>)) {
+        self.abort_proposal = None;
+        self.draw_offer = None;
+        self.takeback_request = None;
+        self.game_state = match finished {
+            (true, winner) => GameState::Finished(winner),
+            // Cross moves first, so it is their turn after an even number of
+            // moves.
+            (false, _) if state.move_count % 2 == 1 => {
+                GameState::InProgress(self.circle.unwrap_or(self.cross))
+            }
+            (false, _) => GameState::InProgress(self.cross),
+        };
+        if let Some(channel) = self.channel.as_mut() {
+            channel.move_count = state.move_count;
+        }
+        self.board = state.board;
+        self.abort_proposal = None;
+        self.draw_offer = None;
+    }
+
+    /// End the game with the final position of the channel, signed by both
+    /// players. Once the channel is disputed, this is only possible during the
+    /// challenge window and with a newer state than the disputed one.
+    fn close_channel(&mut self, state: ChannelState, now: Timestamp) -> ContractResult<()> {
+        let finished = self.check_channel_state(&state)?;
+        ensure!(finished.0, CustomContractError::ChannelNotFinished);
+        if let Some(channel) = self.channel.as_ref() {
+            if let Some(challenge_end) = channel.challenge_end {
+                ensure!(
+                    now <= challenge_end,
+                    CustomContractError::ChallengeWindowClosed
+                );
+                ensure!(
+                    state.move_count > channel.move_count,
+                    CustomContractError::StaleChannelState
+                );
+            }
+        }
+        self.apply_channel_state(state, finished);
+        Ok(())
+    }
+
+    /// Continue the game on-chain from 'state', the last position signed by
+    /// both players, or from the position on-chain if there is none. A
+    /// disputed channel can only be given a newer state during its challenge
+    /// window, which lasts a move timeout. After it, the player in turn has
+    /// another move timeout to move.
+    fn dispute(
+        &mut self,
+        addr: &AccountAddress,
+        state: Option<ChannelState>,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        self.player(addr).ok_or(CustomContractError::NotAPlayer)?;
+        let challenge_end = self
+            .channel
+            .as_ref()
+            .ok_or(CustomContractError::NotAChannel)?
+            .challenge_end;
+        let disputed = challenge_end.is_some();
+        if let Some(challenge_end) = challenge_end {
+            ensure!(
+                now <= challenge_end,
+                CustomContractError::ChallengeWindowClosed
+            );
+        }
+        match state {
+            Some(state) => {
+                let finished = self.check_channel_state(&state)?;
+                if disputed {
+                    let known = self
+                        .channel
+                        .as_ref()
+                        .map_or(0, |channel| channel.move_count);
+                    ensure!(
+                        state.move_count > known,
+                        CustomContractError::StaleChannelState
+                    );
+                }
+                self.apply_channel_state(state, finished);
+            }
+            None => ensure!(!disputed, CustomContractError::StaleChannelState),
+        }
+        if !disputed {
+            let challenge_end = now
+                .checked_add(self.move_timeout)
+                .unwrap_or_else(|| Timestamp::from_timestamp_millis(u64::MAX));
+            if let Some(channel) = self.channel.as_mut() {
+                channel.challenge_end = Some(challenge_end);
+            }
+            self.last_move = challenge_end;
+        }
+        Ok(())
+    }
+
     /// Check whether the proposed 'the_move' is allowed.
-    /// 1. The [Cell] must not be [Cell::Occupied]
-    /// 2. 'the_move' must be within the valid range [0-9]
+    /// 1. The [Cell] must be [Cell::Empty]
+    /// 2. 'the_move' must be on the board
     fn is_valid_move(the_game: &Game, the_move: &PutMove) -> bool {
-        // first check that the [Cell] is not occupied.
-        match the_game.board.0.get(the_move.0).unwrap() {
-            // should be safeish
-            Cell::Empty => true,
-            Cell::Occupied(_) => false,
-        }
+        the_game.board.get(the_move.0) == Some(Cell::Empty)
     }
 
     /// Return whether it's the players turn or not.
@@ -170,84 +1132,310 @@ impl Game {
     }
 
     /// Check if the game is finished.
-    /// 1. There is a winner i.e. a player which has set 3 marks connecting vertically, horizontally or diagonally.
+    /// 1. There is a winner i.e. a player which has set 'win_length' marks connecting vertically, horizontally or diagonally.
     /// 2. There is no winner and no more possible places to put a mark.
+    ///
     /// Every round we check for a winner or if it is a draw, thus we only
-    /// check winning condition based on the provided move and not checking the whole
-    /// board.
-    /// todo: clean this mess up!
+    /// check the lines through the provided move and not the whole board.
     fn is_game_finished(&self, player: &Player, the_move: &PutMove) -> (bool, Option<Player>) {
-        if let (true, winner) = self.horizontal_check(player, the_move) {
-            (true, winner)
-        } else if let (true, winner) = self.vertical_check(player, the_move) {
-            (true, winner)
-        } else if *self.board.0.get(4).unwrap() == Cell::Occupied(*player)
-            && self.diagonal_check(player)
-        {
-            // todo: ideally 'the_move' could be used for only making one check in 'diagonal_check'.
-            // We only check the diagonal if the player who made a move controls the center of the board.
-            // check the diagonals
+        if self.board.is_winning(the_move.0) {
             (true, Some(*player))
-        } else if self.is_draw() {
+        } else if self.board.is_full() {
             (true, None)
         } else {
             (false, None)
         }
     }
 
-    // horizontally winning condition
-    fn horizontal_check(&self, player: &Player, the_move: &PutMove) -> (bool, Option<Player>) {
-        let mut x = true;
-        let row_offset = (the_move.0 / 3) * 3;
-        for i in row_offset..row_offset + 3 {
-            if *self.board.0.get(i).unwrap() != Cell::Occupied(*player) {
-                x = false;
-                break;
-            }
+    /// The number of moves made in the game.
+    fn move_count(&self) -> u32 {
+        match &self.ultimate {
+            Some(ultimate) => ultimate.sub_boards.iter().map(Board::mark_count).sum(),
+            None => self.board.mark_count(),
         }
-        (x, Some(*player))
     }
 
-    fn vertical_check(&self, player: &Player, the_move: &PutMove) -> (bool, Option<Player>) {
-        let mut x = true;
-        let mut column_offset = the_move.0 % 3;
-        for _ in 0..3 {
-            if *self.board.0.get(column_offset).unwrap() != Cell::Occupied(*player) {
-                x = false;
-                break;
-            }
-            column_offset += 3;
+    /// The compact record of the game kept once it is archived.
+    fn summary(&self) -> GameSummary {
+        GameSummary {
+            game_state: self.game_state,
+            cross: self.cross.address(),
+            circle: self.circle.map(|circle| circle.address()),
+            stake: self.stake,
+            move_count: self.move_count(),
+            ended: self.last_move,
+        }
+    }
+
+    /// A short description of the game for listings.
+    fn listing(&self, id: GameId) -> GameListing {
+        GameListing {
+            id,
+            game_state: self.game_state,
+            cross: self.cross.address(),
+            circle: self.circle.map(|circle| circle.address()),
+            stake: self.stake,
         }
-        (x, Some(*player))
     }
 
-    /// Checks whether the player has at least two opposite corners.
-    /// If that is the case, then the player has won as it is
-    /// a precondition to call this function that the player
-    /// controls the middle.
-    /// todo: this is ugly
-    fn diagonal_check(&self, player: &Player) -> bool {
-        let ul = *self.board.0.get(0).unwrap() == Cell::Occupied(*player);
-        let ur = *self.board.0.get(2).unwrap() == Cell::Occupied(*player);
-        let ll = *self.board.0.get(6).unwrap() == Cell::Occupied(*player);
-        let lr = *self.board.0.get(8).unwrap() == Cell::Occupied(*player);
-        ul && lr || ur && ll
+    /// Whether the game has come to an end, one way or another.
+    fn is_over(&self) -> bool {
+        !matches!(
+            self.game_state,
+            GameState::AwaitingOpponent | GameState::InProgress(_) | GameState::AwaitingReveal
+        )
     }
 
-    /// Checks whether every [Cell] is occupied or not.
-    /// Note. This only makes sense in conjunction with the above checks carried
-    /// out before this one.
-    fn is_draw(&self) -> bool {
-        for c in self.board.0.iter() {
-            if *c == Cell::Empty {
-                return false;
+    /// The accounts to pay once the game is over, with the number of stakes
+    /// each of them gets. The winner takes the whole pot, while on a draw or
+    /// when the game is cancelled or aborted every player gets their stake
+    /// back.
+    fn payout_shares(&self) -> Vec<(AccountAddress, u64)> {
+        match (self.game_state, self.circle) {
+            (GameState::Finished(Some(winner)), Some(_)) => vec![(winner.address(), 2)],
+            (GameState::Finished(None), Some(circle)) | (GameState::Aborted, Some(circle)) => {
+                vec![(self.cross.address(), 1), (circle.address(), 1)]
             }
+            (GameState::Cancelled, None) => vec![(self.cross.address(), 1)],
+            _ => vec![],
+        }
+    }
+
+    /// The CCD transfers to make once the game is over.
+    fn payouts(&self) -> Vec<(AccountAddress, Amount)> {
+        if self.stake == Amount::zero() {
+            return vec![];
+        }
+        self.payout_shares()
+            .into_iter()
+            .map(|(to, shares)| (to, self.stake * shares))
+            .collect()
+    }
+
+    /// The token transfers to make once the game is over, if it is staked in
+    /// tokens.
+    fn token_payouts(&self) -> Vec<(AccountAddress, TokenAmount)> {
+        match &self.token_stake {
+            Some(stake) if stake.amount.0 > 0 => self
+                .payout_shares()
+                .into_iter()
+                .map(|(to, shares)| (to, TokenAmount(stake.amount.0 * shares)))
+                .collect(),
+            _ => vec![],
         }
-        true
     }
 }
 
-/// The different errors the contract can produce.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy, SchemaType)]
+pub enum MatchState {
+    AwaitingOpponent,
+    InProgress,
+    Finished(Option<AccountAddress>), // None if the match was tied or a game was aborted, otherwise the winner.
+}
+
+/// A series of games between the same two accounts. The creator plays cross
+/// in the first game and the colours alternate from there. The match is won
+/// by the first account to win more than half of the games.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+struct Match {
+    match_state: MatchState,
+    creator: AccountAddress,
+    opponent: Option<AccountAddress>,
+    /// The most games the match lasts.
+    best_of: u32,
+    creator_wins: u32,
+    opponent_wins: u32,
+    /// The games played so far. The last one is the current game.
+    games: Vec<GameId>,
+    /// How the games of the match are played. 'opponent' makes the match
+    /// private.
+    settings: CreateGameParams,
+}
+
+impl Match {
+    fn new(creator: AccountAddress, params: CreateMatchParams) -> Self {
+        Match {
+            match_state: MatchState::AwaitingOpponent,
+            creator,
+            opponent: None,
+            best_of: params.best_of,
+            creator_wins: 0,
+            opponent_wins: 0,
+            games: Vec::new(),
+            settings: params.settings,
+        }
+    }
+
+    fn join(&mut self, opponent: AccountAddress) -> ContractResult<()> {
+        ensure!(
+            self.match_state == MatchState::AwaitingOpponent,
+            CustomContractError::InvalidJoin
+        );
+        ensure!(self.creator != opponent, CustomContractError::InvalidJoin);
+        if let Some(invited) = self.settings.opponent {
+            ensure!(opponent == invited, CustomContractError::NotInvited);
+        }
+        self.opponent = Some(opponent);
+        self.match_state = MatchState::InProgress;
+        Ok(())
+    }
+
+    /// The accounts playing cross and circle in the next game.
+    fn next_seats(&self) -> ContractResult<(AccountAddress, AccountAddress)> {
+        let opponent = self.opponent.ok_or(CustomContractError::InvalidGameState)?;
+        // The colours swap every game.
+        if self.games.len() % 2 == 1 {
+            Ok((opponent, self.creator))
+        } else {
+            Ok((self.creator, opponent))
+        }
+    }
+
+    /// Count the outcome of the current game, which is over, and finish the
+    /// match if it is decided or all games are played. An aborted game ends
+    /// the match without a winner.
+    fn record(&mut self, the_game: &Game) {
+        match the_game.game_state {
+            GameState::Finished(Some(winner)) if winner.address() == self.creator => {
+                self.creator_wins += 1
+            }
+            GameState::Finished(Some(_)) => self.opponent_wins += 1,
+            GameState::Finished(None) => (),
+            _ => {
+                self.match_state = MatchState::Finished(None);
+                return;
+            }
+        }
+        let majority = self.best_of / 2 + 1;
+        let all_played = self.games.len() as u32 >= self.best_of;
+        if self.creator_wins >= majority || all_played && self.creator_wins > self.opponent_wins {
+            self.match_state = MatchState::Finished(Some(self.creator));
+        } else if self.opponent_wins >= majority
+            || all_played && self.opponent_wins > self.creator_wins
+        {
+            self.match_state = MatchState::Finished(self.opponent);
+        } else if all_played {
+            self.match_state = MatchState::Finished(None);
+        }
+    }
+}
+
+/// The largest number of players in a tournament.
+const MAX_TOURNAMENT_SIZE: u32 = 64;
+/// The share of the prize pool, in percent, paid to the runner-up of a
+/// tournament. The winner gets the rest.
+const RUNNER_UP_SHARE: u64 = 25;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy, SchemaType)]
+pub enum TournamentState {
+    /// Players can register until the tournament is full.
+    Registering,
+    /// The games of 'round' are played, counting from 1.
+    InProgress { round: u32 },
+    Finished {
+        winner: AccountAddress,
+        runner_up: AccountAddress,
+    },
+    /// The organizer called the tournament off before it was full.
+    Cancelled,
+}
+
+/// A single-elimination tournament. Once all players have registered they
+/// are paired in registration order, and the winners of each round are
+/// paired again until one player is left. A drawn or aborted game is
+/// replayed with the colours swapped.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+struct Tournament {
+    tournament_state: TournamentState,
+    organizer: AccountAddress,
+    /// The number of players, a power of two.
+    size: u32,
+    /// The CCD each player pays to register. The fees make up the prizes.
+    entry_fee: Amount,
+    /// The players in registration order.
+    players: Vec<AccountAddress>,
+    /// The players left in the current round. The players at '2 * i' and
+    /// '2 * i + 1' play pairing 'i', the first one as cross.
+    bracket: Vec<AccountAddress>,
+    /// The current game of each pairing of the round.
+    current_games: Vec<GameId>,
+    /// The winner of each pairing of the round, once decided.
+    winners: Vec<Option<AccountAddress>>,
+    /// All games of the tournament, including replays.
+    games: Vec<GameId>,
+    /// How the games of the tournament are played.
+    settings: CreateGameParams,
+}
+
+impl Tournament {
+    fn new(organizer: AccountAddress, params: CreateTournamentParams) -> Self {
+        Tournament {
+            tournament_state: TournamentState::Registering,
+            organizer,
+            size: params.size,
+            entry_fee: params.entry_fee,
+            players: Vec::new(),
+            bracket: Vec::new(),
+            current_games: Vec::new(),
+            winners: Vec::new(),
+            games: Vec::new(),
+            settings: params.settings,
+        }
+    }
+
+    /// Register 'player', who paid 'fee'. Returns whether the tournament is
+    /// now full and can start.
+    fn register(&mut self, player: AccountAddress, fee: Amount) -> ContractResult<bool> {
+        ensure!(
+            self.tournament_state == TournamentState::Registering,
+            CustomContractError::RegistrationClosed
+        );
+        ensure!(
+            !self.players.contains(&player),
+            CustomContractError::AlreadyRegistered
+        );
+        ensure!(fee == self.entry_fee, CustomContractError::WrongStake);
+        self.players.push(player);
+        Ok(self.players.len() as u32 == self.size)
+    }
+
+    fn cancel(&mut self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(
+            self.tournament_state == TournamentState::Registering,
+            CustomContractError::CannotCancel
+        );
+        ensure!(self.organizer == *addr, CustomContractError::NotTheCreator);
+        self.tournament_state = TournamentState::Cancelled;
+        Ok(())
+    }
+
+    /// The transfers to make once the tournament is over. The runner-up gets
+    /// [RUNNER_UP_SHARE] percent of the entry fees and the winner the rest,
+    /// while a cancelled tournament refunds every player.
+    fn prizes(&self) -> Vec<(AccountAddress, Amount)> {
+        if self.entry_fee == Amount::zero() {
+            return vec![];
+        }
+        match self.tournament_state {
+            TournamentState::Finished { winner, runner_up } => {
+                let pool = self.entry_fee.micro_ccd * self.players.len() as u64;
+                let runner_up_prize = pool * RUNNER_UP_SHARE / 100;
+                vec![
+                    (winner, Amount::from_micro_ccd(pool - runner_up_prize)),
+                    (runner_up, Amount::from_micro_ccd(runner_up_prize)),
+                ]
+            }
+            TournamentState::Cancelled => self
+                .players
+                .iter()
+                .map(|player| (*player, self.entry_fee))
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// The different errors the contract can produce.
 #[derive(Serialize, Debug, PartialEq, Eq, Reject, SchemaType)]
 enum CustomContractError {
     #[from(ParseError)]
@@ -258,6 +1446,132 @@ enum CustomContractError {
     InvalidMove,
     NotAHuman,
     InvalidGameState,
+    /// The amount sent when joining does not match the stake of the game.
+    WrongStake,
+    #[from(TransferError)]
+    InvokeTransferError,
+    /// The deadline for making the move has passed.
+    MoveTimedOut,
+    /// Only the player waiting for the opponent can claim a timeout.
+    InvalidTimeoutClaim,
+    DeadlineNotPassed,
+    /// The sender is not playing in the game.
+    NotAPlayer,
+    /// Only games in progress can be resigned.
+    CannotResign,
+    /// Only games nobody has joined yet can be cancelled.
+    CannotCancel,
+    /// Only the creator of a game can cancel it.
+    NotTheCreator,
+    /// Only games in progress can be aborted.
+    CannotAbort,
+    /// There is already a pending proposal to abort the game.
+    AbortAlreadyProposed,
+    /// The opponent has not proposed to abort the game.
+    NoAbortProposal,
+    /// The game is private and the sender is not the invited opponent.
+    NotInvited,
+    /// The board size or win length is out of range.
+    InvalidBoard,
+    /// The compact game view only supports 3×3 boards.
+    UnsupportedBoard,
+    /// The move is not in the sub-board the player was sent to.
+    WrongSubBoard,
+    /// The game is not an ultimate game.
+    NotUltimate,
+    /// The creator committed to a secret, so the joiner has to as well.
+    MissingCommitment,
+    /// The player already revealed their secret.
+    AlreadyRevealed,
+    /// The secret does not match the commitment of the player.
+    WrongSecret,
+    /// The match id does not exist.
+    InvalidMatchId,
+    /// A match needs at least one game, and its seats alternate instead of
+    /// being drawn.
+    InvalidMatch,
+    /// The tournament id does not exist.
+    InvalidTournamentId,
+    /// A tournament needs a power of two between 2 and [MAX_TOURNAMENT_SIZE]
+    /// players, and its seats are not drawn.
+    InvalidTournament,
+    /// The tournament is not open for registration.
+    RegistrationClosed,
+    /// The player is already registered for the tournament.
+    AlreadyRegistered,
+    /// Only the owner, or for pausing an admin, may do this.
+    Unauthorized,
+    /// The contract is paused, so no games can be started or played.
+    ContractPaused,
+    /// The module to upgrade to is missing or is not a smart contract module
+    /// of the same protocol version.
+    #[from(UpgradeError)]
+    FailedUpgrade,
+    /// The call to migrate the state after an upgrade failed.
+    FailedMigration,
+    /// The state is newer than this version of the contract understands.
+    UnknownStateVersion,
+    /// A draw offer or a takeback request is already pending.
+    OfferAlreadyMade,
+    /// The opponent has not offered a draw or asked to take back a move.
+    NoPendingOffer,
+    /// Only the last move of a game in progress can be taken back, by the
+    /// player who made it.
+    CannotTakeBack,
+    /// It is not the turn of an account that is played by a bot.
+    NotABotTurn,
+    /// The bot failed, or returned a move that is not allowed.
+    BotFailed,
+    /// A bot tried to call back into the contract while making its move.
+    Reentrancy,
+    /// The permit is not signed by the signer.
+    WrongSignature,
+    /// The permit is for another contract.
+    WrongContract,
+    /// The expiry of the permit has passed.
+    PermitExpired,
+    /// The permit does not carry the next nonce of the signer.
+    WrongNonce,
+    /// The game is not played in a channel.
+    NotAChannel,
+    /// The moves of the game are played off-chain, until it is disputed.
+    ChannelOpen,
+    /// The channel is disputed, and until the challenge window has passed only
+    /// a newer signed state can be submitted.
+    ChallengeWindowOpen,
+    /// The challenge window of the disputed channel has passed.
+    ChallengeWindowClosed,
+    /// The channel state is not a position of the game reached by alternate
+    /// moves.
+    InvalidChannelState,
+    /// The channel state is older than the one submitted on-chain.
+    StaleChannelState,
+    /// The channel state does not end the game.
+    ChannelNotFinished,
+    /// There is no trophy with the given token id.
+    InvalidTokenId,
+    /// The sender of the transfer does not hold the trophy.
+    InsufficientFunds,
+    /// The metadata URL is longer than [MAX_METADATA_URL_LENGTH].
+    MetadataUrlTooLong,
+    /// The contract receiving a trophy rejected it.
+    TransferRejected,
+    /// Only token contracts can send tokens to stake on a game.
+    NotATokenContract,
+    /// The token contract of the game refused to pay out its stakes.
+    TokenTransferFailed,
+    /// The admins have not allowed games to be staked in the token.
+    TokenNotAllowed,
+    /// The move timeout is shorter than [MIN_MOVE_TIMEOUT].
+    InvalidMoveTimeout,
+    /// The commitment of the creator is bound to another game id.
+    WrongGameId,
+    /// Only the summary of the game is kept since it was archived.
+    GameArchived,
+    /// The owner has not set where the metadata of the trophies is found.
+    MetadataUrlNotSet,
+    #[from(LogError)]
+    LogError,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -270,22 +1584,31 @@ impl PutMove {
     }
 }
 
-impl Index<PutMove> for Board {
-    type Output = Cell;
+type ContractResult<A> = Result<A, CustomContractError>;
+
+/// The result of the CIS-2 entrypoints, which report the errors of the
+/// standard with the codes it fixes.
+type Cis2Result<A> = Result<A, Cis2Error<CustomContractError>>;
 
-    fn index(&self, index: PutMove) -> &Self::Output {
-        &self.0[index.0]
+impl From<CustomContractError> for Cis2Error<CustomContractError> {
+    fn from(error: CustomContractError) -> Self {
+        match error {
+            CustomContractError::InvalidTokenId => Cis2Error::InvalidTokenId,
+            CustomContractError::InsufficientFunds => Cis2Error::InsufficientFunds,
+            CustomContractError::Unauthorized => Cis2Error::Unauthorized,
+            error => Cis2Error::Custom(error),
+        }
     }
 }
 
-type ContractResult<A> = Result<A, CustomContractError>;
-
 #[derive(Serialize, SchemaType)]
 pub struct ViewState {
     pub games: collections::BTreeMap<u64, Game>,
 }
 
-#[receive(contract = "tictactoe", name = "view")]
+/// All games being played, with their full state. Archived games are left
+/// out, their summaries are returned by 'game_summary' and 'list_games'.
+#[receive(contract = "tictactoe", name = "view", return_value = "ViewState")]
 fn tictactoe_view<S: HasStateApi>(
     _ctx: &impl HasReceiveContext,
     host: &impl HasHost<State<S>, StateApiType = S>,
@@ -298,7 +1621,276 @@ fn tictactoe_view<S: HasStateApi>(
     Ok(ViewState { games })
 }
 
-#[receive(contract = "tictactoe", name = "game_view")]
+/// The maximum number of games returned by 'list_games'.
+const MAX_PAGE_SIZE: u32 = 100;
+/// The maximum number of game ids 'list_games' looks at in one invocation.
+/// This bounds the energy used when filters match only a few games.
+const MAX_PAGE_SCAN: u64 = 1000;
+
+#[derive(Serialize, SchemaType)]
+struct ListGamesParams {
+    /// The game id to start listing from.
+    start: GameId,
+    /// The maximum number of games to return, at most [MAX_PAGE_SIZE].
+    limit: u32,
+    /// Only list games in this state.
+    state: Option<GameStateKind>,
+    /// Only list games this account plays in.
+    account: Option<AccountAddress>,
+}
+
+impl ListGamesParams {
+    fn matches(&self, listing: &GameListing) -> bool {
+        let state_matches = match self.state {
+            Some(kind) => listing.game_state.kind() == kind,
+            None => true,
+        };
+        let account_matches = match self.account {
+            Some(account) => listing.cross == account || listing.circle == Some(account),
+            None => true,
+        };
+        state_matches && account_matches
+    }
+}
+
+/// A short description of a game.
+#[derive(Debug, Serialize, SchemaType)]
+pub struct GameListing {
+    pub id: GameId,
+    pub game_state: GameState,
+    pub cross: AccountAddress,
+    pub circle: Option<AccountAddress>,
+    pub stake: Amount,
+}
+
+/// What is kept of a finished game once it is archived by 'prune_finished'.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, SchemaType)]
+pub struct GameSummary {
+    pub game_state: GameState,
+    pub cross: AccountAddress,
+    pub circle: Option<AccountAddress>,
+    pub stake: Amount,
+    pub move_count: u32,
+    /// When the game ended.
+    pub ended: Timestamp,
+}
+
+impl GameSummary {
+    fn listing(&self, id: GameId) -> GameListing {
+        GameListing {
+            id,
+            game_state: self.game_state,
+            cross: self.cross,
+            circle: self.circle,
+            stake: self.stake,
+        }
+    }
+}
+
+/// One page of games.
+#[derive(Debug, Serialize, SchemaType)]
+pub struct GamePage {
+    pub games: Vec<GameListing>,
+    /// The 'start' of the next page, or None if there are no more games.
+    /// The page can contain fewer games than the limit even when there are
+    /// more games to list.
+    pub next: Option<GameId>,
+}
+
+/// The id the next game created gets, which the commitment of a creator
+/// drawing seats is bound to.
+#[receive(contract = "tictactoe", name = "next_game_id", return_value = "u64")]
+fn tictactoe_next_game_id<S: HasStateApi>(
+    _ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<GameId> {
+    Ok(host.state().ctr)
+}
+
+/// List the games page by page, optionally filtered by state or player.
+#[receive(
+    contract = "tictactoe",
+    name = "list_games",
+    parameter = "ListGamesParams",
+    return_value = "GamePage"
+)]
+fn tictactoe_list_games<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<GamePage> {
+    let params: ListGamesParams = ctx.parameter_cursor().get()?;
+    Ok(host.state().list_games(&params))
+}
+
+#[derive(Serialize, SchemaType)]
+struct AccountParams {
+    account: AccountAddress,
+}
+
+/// The ids of all the games an account plays in.
+#[receive(
+    contract = "tictactoe",
+    name = "games_of",
+    parameter = "AccountParams",
+    return_value = "Vec<GameId>"
+)]
+fn tictactoe_games_of<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<Vec<GameId>> {
+    let params: AccountParams = ctx.parameter_cursor().get()?;
+    Ok(host.state().games_of(&params.account))
+}
+
+/// The rating every account starts with.
+const INITIAL_RATING: u32 = 1200;
+/// The largest change in rating a single game can cause.
+const ELO_K: i64 = 32;
+/// The expected score, in thousandths, of a player rated 0, 25, 50, ..., 400
+/// points above the opponent. Larger differences count as 400.
+const EXPECTED_SCORES: [i64; 17] = [
+    500, 536, 571, 606, 640, 673, 703, 733, 760, 785, 808, 830, 849, 867, 882, 896, 909,
+];
+
+/// The expected score, in thousandths, of a player rated 'rating' against an
+/// opponent rated 'opponent'. Contracts cannot use floating point, so the
+/// Elo curve is interpolated from [EXPECTED_SCORES].
+fn expected_score(rating: u32, opponent: u32) -> i64 {
+    let diff = (i64::from(rating) - i64::from(opponent)).clamp(-400, 400);
+    let step = (diff.abs() / 25) as usize;
+    let lower = EXPECTED_SCORES[step];
+    let upper = EXPECTED_SCORES[(step + 1).min(EXPECTED_SCORES.len() - 1)];
+    let score = lower + (upper - lower) * (diff.abs() % 25) / 25;
+    if diff >= 0 {
+        score
+    } else {
+        1000 - score
+    }
+}
+
+/// The results of an account's finished games and its Elo rating.
+/// Cancelled and aborted games are not counted.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy, SchemaType)]
+pub struct PlayerStats {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// The number of games won in a row, or minus the number of games lost
+    /// in a row. A draw resets it to zero.
+    pub streak: i32,
+    pub rating: u32,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        PlayerStats {
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            streak: 0,
+            rating: INITIAL_RATING,
+        }
+    }
+}
+
+impl PlayerStats {
+    /// Record a game with the given score, in thousandths (1000 for a win, 500
+    /// for a draw and 0 for a loss), against an opponent rated
+    /// 'opponent_rating'.
+    fn record(&mut self, score: i64, opponent_rating: u32) {
+        match score {
+            1000 => {
+                self.wins += 1;
+                self.streak = self.streak.max(0) + 1;
+            }
+            0 => {
+                self.losses += 1;
+                self.streak = self.streak.min(0) - 1;
+            }
+            _ => {
+                self.draws += 1;
+                self.streak = 0;
+            }
+        }
+        let change = ELO_K * (score - expected_score(self.rating, opponent_rating)) / 1000;
+        self.rating = (i64::from(self.rating) + change).max(0) as u32;
+    }
+}
+
+/// The statistics of an account. Accounts that have not finished a game yet
+/// get the default statistics.
+#[receive(
+    contract = "tictactoe",
+    name = "player_stats",
+    parameter = "AccountParams",
+    return_value = "PlayerStats"
+)]
+fn tictactoe_player_stats<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<PlayerStats> {
+    let params: AccountParams = ctx.parameter_cursor().get()?;
+    Ok(host.state().player_stats(&params.account))
+}
+
+#[derive(Serialize, SchemaType)]
+struct LeaderboardParams {
+    /// The number of accounts to return, at most [MAX_PAGE_SIZE].
+    top: u32,
+}
+
+/// The position of an account in the leaderboard. The state iterates a set in
+/// the order of the serialized keys, so the rating and the wins are stored
+/// inverted and big-endian to put the highest rating first, and the most wins
+/// first among equal ratings.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy)]
+struct RankKey {
+    rating: [u8; 4],
+    wins: [u8; 4],
+    account: AccountAddress,
+}
+
+impl RankKey {
+    fn new(account: AccountAddress, stats: &PlayerStats) -> Self {
+        RankKey {
+            rating: (u32::MAX - stats.rating).to_be_bytes(),
+            wins: (u32::MAX - stats.wins).to_be_bytes(),
+            account,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, SchemaType)]
+pub struct LeaderboardEntry {
+    pub account: AccountAddress,
+    pub stats: PlayerStats,
+}
+
+/// The highest rated accounts, best first.
+#[receive(
+    contract = "tictactoe",
+    name = "leaderboard",
+    parameter = "LeaderboardParams",
+    return_value = "Vec<LeaderboardEntry>"
+)]
+fn tictactoe_leaderboard<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<Vec<LeaderboardEntry>> {
+    let params: LeaderboardParams = ctx.parameter_cursor().get()?;
+    Ok(host
+        .state()
+        .leaderboard(params.top.min(MAX_PAGE_SIZE) as usize))
+}
+
+/// The game on a classic board packed in a u32, see [COMPACT_GAME_VERSION]
+/// and the conversion from [Game].
+#[receive(
+    contract = "tictactoe",
+    name = "game_view",
+    parameter = "JoinParams",
+    return_value = "u32"
+)]
 fn tictactoe_game_view<S: HasStateApi>(
     ctx: &impl HasReceiveContext,
     host: &impl HasHost<State<S>, StateApiType = S>,
@@ -306,270 +1898,4988 @@ fn tictactoe_game_view<S: HasStateApi>(
     // Parse the 'JoinParams'
     let params: JoinParams = ctx.parameter_cursor().get()?;
 
-    let the_state = host.state();
-    let the_game = the_state
-        .games
-        .get(&params.game_id)
-        .ok_or(CustomContractError::InvalidGameId)?;
+    let the_game = host.state().game(params.game_id)?;
+    ensure!(
+        the_game.board.size == 3,
+        CustomContractError::UnsupportedBoard.into()
+    );
     Ok(the_game.clone().into())
 }
 
-#[receive(contract = "tictactoe", name = "game_view_players")]
-fn tictactoe_game_view_players<S: HasStateApi>(
+/// The board of a game, for boards of any size.
+#[receive(
+    contract = "tictactoe",
+    name = "game_board",
+    parameter = "JoinParams",
+    return_value = "Board"
+)]
+fn tictactoe_game_board<S: HasStateApi>(
     ctx: &impl HasReceiveContext,
     host: &impl HasHost<State<S>, StateApiType = S>,
-) -> ReceiveResult<Vec<u8>> {
+) -> ReceiveResult<Board> {
     let params: JoinParams = ctx.parameter_cursor().get()?;
-    let the_game = host
-        .state()
-        .games
-        .get(&params.game_id)
-        .ok_or(CustomContractError::InvalidGameId)?;
-    let mut out: Vec<u8> = vec![];
-    // don't judge - not much time left when I realized my stupidity.
-    match the_game.cross {
-        Player::Cross(p) => {
-            out.extend_from_slice(&p.0);
-        }
-        Player::Circle(p) => {
-            out.extend_from_slice(&p.0);
+    let the_game = host.state().game(params.game_id)?;
+    Ok(the_game.board.clone())
+}
+
+/// The sub-boards of an ultimate game, including the sub-board the next move
+/// has to be in.
+#[receive(
+    contract = "tictactoe",
+    name = "ultimate_board",
+    parameter = "JoinParams",
+    return_value = "UltimateBoard"
+)]
+fn tictactoe_ultimate_board<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<UltimateBoard> {
+    let params: JoinParams = ctx.parameter_cursor().get()?;
+    let the_game = host.state().game(params.game_id)?;
+    Ok(the_game
+        .ultimate
+        .clone()
+        .ok_or(CustomContractError::NotUltimate)?)
+}
+
+#[derive(Serialize, SchemaType)]
+struct HistoryParams {
+    game_id: GameId,
+    /// The number of the first move to return, counting from 0.
+    start: u32,
+    /// The maximum number of moves to return, at most [MAX_PAGE_SIZE].
+    limit: u32,
+}
+
+/// A page of the moves of a game.
+#[derive(Debug, PartialEq, Eq, Serialize, SchemaType)]
+pub struct HistoryPage {
+    pub moves: Vec<MoveRecord>,
+    /// The 'start' of the next page, or None if there are no more moves.
+    pub next: Option<u32>,
+}
+
+/// The moves of a game in the order they were made, to replay or analyse it,
+/// one page at a time. The history of archived games is not kept, so they
+/// fail with 'GameArchived'.
+#[receive(
+    contract = "tictactoe",
+    name = "game_history",
+    parameter = "HistoryParams",
+    return_value = "HistoryPage"
+)]
+fn tictactoe_game_history<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<HistoryPage> {
+    let params: HistoryParams = ctx.parameter_cursor().get()?;
+    Ok(host.state().history_page(&params)?)
+}
+
+/// The outcome, players and number of moves of a game, whether it is still
+/// stored in full or has been archived.
+#[receive(
+    contract = "tictactoe",
+    name = "game_summary",
+    parameter = "JoinParams",
+    return_value = "GameSummary"
+)]
+fn tictactoe_game_summary<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<GameSummary> {
+    let params: JoinParams = ctx.parameter_cursor().get()?;
+    Ok(host.state().summary(params.game_id)?)
+}
+
+#[derive(Serialize, SchemaType)]
+struct PruneParams {
+    /// The game id to start from.
+    start: GameId,
+    /// Only archive games that ended at least this long ago.
+    older_than: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, SchemaType)]
+struct PruneResult {
+    /// The number of games archived.
+    pruned: u32,
+    /// The 'start' to continue from, or None if all games have been looked at.
+    next: Option<GameId>,
+}
+
+/// Move finished games that ended more than 'older_than' ago out of the state
+/// and into the archive, keeping only their [GameSummary]. Looks at up to
+/// [MAX_PAGE_SCAN] game ids from 'start'. Only the owner and the admins can do
+/// this.
+#[receive(
+    contract = "tictactoe",
+    name = "prune_finished",
+    parameter = "PruneParams",
+    return_value = "PruneResult",
+    mutable
+)]
+fn contract_prune_finished<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+) -> ContractResult<PruneResult> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            ensure!(
+                host.state().is_admin(&addr),
+                CustomContractError::Unauthorized
+            );
+            let params: PruneParams = ctx.parameter_cursor().get()?;
+            let cutoff = ctx
+                .metadata()
+                .slot_time()
+                .checked_sub(params.older_than)
+                .unwrap_or_else(|| Timestamp::from_timestamp_millis(0));
+            Ok(host.state_mut().prune_finished(params.start, cutoff))
         }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
-    match the_game.circle {
-        Some(p) => match p {
-            Player::Cross(p) => {
-                out.extend_from_slice(&p.0);
-            }
-            Player::Circle(p) => {
-                out.extend_from_slice(&p.0);
-            }
-        },
-        None => (),
-    }
-    Ok(out)
+}
+
+/// The accounts playing a game.
+#[derive(Debug, PartialEq, Eq, Serialize, SchemaType)]
+pub struct Players {
+    pub cross: AccountAddress,
+    /// None until the opponent has joined.
+    pub circle: Option<AccountAddress>,
+}
+
+/// The players of a game, also once it is archived.
+#[receive(
+    contract = "tictactoe",
+    name = "game_view_players",
+    parameter = "JoinParams",
+    return_value = "Players"
+)]
+fn tictactoe_game_view_players<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<Players> {
+    let params: JoinParams = ctx.parameter_cursor().get()?;
+    let summary = host.state().summary(params.game_id)?;
+    Ok(Players {
+        cross: summary.cross,
+        circle: summary.circle,
+    })
+}
+
+/// The events logged by the contract, so that off-chain tools can follow
+/// the games without querying the whole state. The events of the trophies
+/// carry the tags of the CIS-2 standard, so that wallets pick them up.
+#[derive(Debug, Serialize, SchemaType)]
+#[concordium(repr(u8))]
+enum Event {
+    /// A new game was created by 'cross'.
+    GameCreated { id: GameId, cross: AccountAddress },
+    /// 'circle' joined the game and it is now in progress, or waiting for
+    /// both players to reveal their secrets.
+    PlayerJoined { id: GameId, circle: AccountAddress },
+    /// 'player' put a mark in 'cell'.
+    MoveMade {
+        id: GameId,
+        player: Player,
+        cell: u64,
+    },
+    /// The game is over. This covers games that were won or drawn as well as
+    /// cancelled and aborted ones.
+    GameFinished { id: GameId, outcome: GameState },
+    /// Both secrets were revealed and the game starts with 'cross' to move.
+    SeatsDrawn {
+        id: GameId,
+        cross: AccountAddress,
+        circle: AccountAddress,
+    },
+    /// A new match was created by 'creator'.
+    MatchCreated {
+        id: MatchId,
+        creator: AccountAddress,
+    },
+    /// 'opponent' joined the match and its first game started.
+    MatchJoined {
+        id: MatchId,
+        opponent: AccountAddress,
+    },
+    /// The match is over.
+    MatchFinished { id: MatchId, outcome: MatchState },
+    /// A new tournament was created by 'organizer'.
+    TournamentCreated {
+        id: TournamentId,
+        organizer: AccountAddress,
+    },
+    /// 'player' registered for the tournament.
+    PlayerRegistered {
+        id: TournamentId,
+        player: AccountAddress,
+    },
+    /// The tournament is over, or it was cancelled.
+    TournamentFinished {
+        id: TournamentId,
+        outcome: TournamentState,
+    },
+    /// The contract is now owned by 'new_owner'.
+    OwnershipTransferred {
+        previous_owner: AccountAddress,
+        new_owner: AccountAddress,
+    },
+    /// 'account' was made an admin, or is no longer one.
+    AdminUpdated {
+        account: AccountAddress,
+        is_admin: bool,
+    },
+    /// The contract was paused or unpaused.
+    PauseUpdated { paused: bool },
+    /// The contract was upgraded to the smart contract module 'module'.
+    Upgraded { module: ModuleReference },
+    /// The state was migrated to a new layout.
+    Migrated { from_version: u32, to_version: u32 },
+    /// 'account' is now played by the contract 'bot', or by nobody if None.
+    BotUpdated {
+        account: AccountAddress,
+        bot: Option<ContractAddress>,
+    },
+    /// The permit of 'account' with 'nonce' was used.
+    Nonce { account: AccountAddress, nonce: u64 },
+    /// The channel of the game was disputed by 'player', at the position
+    /// after 'move_count' moves.
+    ChannelDisputed {
+        id: GameId,
+        player: AccountAddress,
+        move_count: u32,
+    },
+    /// The last move of 'player', which marked 'cell', was taken back.
+    MoveTakenBack {
+        id: GameId,
+        player: Player,
+        cell: u64,
+    },
+    /// Games can now be staked in the token 'token_id' of 'contract', or no
+    /// longer can.
+    TokenAllowed {
+        contract: ContractAddress,
+        token_id: TokenIdVec,
+        allowed: bool,
+    },
+    /// CIS-2: 'amount' of the trophy 'token_id' moved from 'from' to 'to'.
+    #[concordium(tag = 255)]
+    TrophyTransferred {
+        token_id: TokenId,
+        amount: TokenAmount,
+        from: Address,
+        to: Address,
+    },
+    /// CIS-2: the trophy 'token_id' was minted to 'owner', the winner of the
+    /// game.
+    #[concordium(tag = 254)]
+    TrophyMinted {
+        token_id: TokenId,
+        amount: TokenAmount,
+        owner: Address,
+    },
+    /// CIS-2: 'owner' added or removed 'operator'.
+    #[concordium(tag = 252)]
+    OperatorUpdated {
+        update: OperatorUpdate,
+        owner: Address,
+        operator: Address,
+    },
+    /// CIS-2: the metadata of the trophy 'token_id' is found at
+    /// 'metadata_url'. Only logged once the owner has set where the metadata
+    /// is found.
+    #[concordium(tag = 251)]
+    TokenMetadata {
+        token_id: TokenId,
+        metadata_url: MetadataUrl,
+    },
 }
 
 /// The init function of the contract
-#[init(contract = "tictactoe")]
+#[init(contract = "tictactoe", event = "Event")]
 fn contract_init<S: HasStateApi>(
-    _ctx: &impl HasInitContext,
+    ctx: &impl HasInitContext,
     state_builder: &mut StateBuilder<S>,
 ) -> InitResult<State<S>> {
-    // Create an empty state
-    Ok(State::empty(state_builder))
+    // Create an empty state, owned by the account initializing the contract
+    Ok(State::empty(ctx.init_origin(), state_builder))
 }
 
-#[receive(contract = "tictactoe", name = "create_game", mutable)]
-fn contract_create<S: HasStateApi>(
+#[derive(Serialize, SchemaType)]
+struct TransferOwnershipParams {
+    new_owner: AccountAddress,
+}
+
+/// Hand the contract over to another account. Only the owner can do this.
+#[receive(
+    contract = "tictactoe",
+    name = "transfer_ownership",
+    parameter = "TransferOwnershipParams",
+    mutable,
+    enable_logger
+)]
+fn contract_transfer_ownership<S: HasStateApi>(
     ctx: &impl HasReceiveContext,
     host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
 ) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
     match ctx.sender() {
         Address::Account(addr) => {
-            let (state, _) = host.state_and_builder();
-            state.create_game(addr); // this is cross.
+            let params: TransferOwnershipParams = ctx.parameter_cursor().get()?;
+            let state = host.state_mut();
+            state.ensure_owner(&addr)?;
+            state.owner = params.new_owner;
+            logger.log(&Event::OwnershipTransferred {
+                previous_owner: addr,
+                new_owner: params.new_owner,
+            })?;
             Ok(())
         }
         Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
 }
 
+/// The longest base URL of the trophy metadata, so that the
+/// 'TokenMetadata' event fits in the 512 bytes of an event.
+const MAX_METADATA_URL_LENGTH: usize = 256;
+
 #[derive(Serialize, SchemaType)]
-struct JoinParams {
-    game_id: u64,
+struct SetMetadataUrlParams {
+    /// The URL the token id of a trophy is appended to in hex.
+    base_url: String,
 }
 
+/// Set where the metadata of the trophies is found, see
+/// [State::trophy_metadata_url]. Only the owner can do this. Trophies minted
+/// from now on log the new URL, and 'tokenMetadata' returns it for all of
+/// them. An empty URL stops both until a new one is set.
 #[receive(
     contract = "tictactoe",
-    name = "join_game",
-    parameter = "JoinParams",
-    return_value = "bool",
+    name = "set_metadata_url",
+    parameter = "SetMetadataUrlParams",
     mutable
 )]
-fn contract_join<S: HasStateApi>(
+fn contract_set_metadata_url<S: HasStateApi>(
     ctx: &impl HasReceiveContext,
     host: &mut impl HasHost<State<S>, StateApiType = S>,
 ) -> ContractResult<()> {
-    // Parse the 'JoinParams'
-    let params: JoinParams = ctx.parameter_cursor().get()?;
-    // Get the sender of the transaction
+    host.state().ensure_no_bot_call()?;
     match ctx.sender() {
         Address::Account(addr) => {
-            let (state, _) = host.state_and_builder();
-            state.join(params.game_id, Player::Circle(addr))?;
+            let params: SetMetadataUrlParams = ctx.parameter_cursor().get()?;
+            ensure!(
+                params.base_url.len() <= MAX_METADATA_URL_LENGTH,
+                CustomContractError::MetadataUrlTooLong
+            );
+            let state = host.state_mut();
+            state.ensure_owner(&addr)?;
+            state.metadata_base_url = params.base_url;
             Ok(())
         }
-        // We only allow humans to play.
         Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
 }
 
 #[derive(Serialize, SchemaType)]
-struct MakeMoveParams {
-    game_id: u64,
-    the_move: u64,
+struct SetAdminParams {
+    account: AccountAddress,
+    /// Whether to grant or revoke the admin role.
+    is_admin: bool,
 }
 
+/// Grant or revoke the admin role. Only the owner can do this.
 #[receive(
     contract = "tictactoe",
-    name = "make_move",
-    parameter = "MakeMoveParams",
-    mutable
+    name = "set_admin",
+    parameter = "SetAdminParams",
+    mutable,
+    enable_logger
 )]
-fn contract_make_move<S: HasStateApi>(
+fn contract_set_admin<S: HasStateApi>(
     ctx: &impl HasReceiveContext,
     host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
 ) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
     match ctx.sender() {
         Address::Account(addr) => {
-            let params: MakeMoveParams = ctx.parameter_cursor().get()?;
-            let (state, _) = host.state_and_builder();
-            let the_move = PutMove::new(params.the_move as usize);
-            state.make_move(params.game_id, &addr, the_move)
+            let params: SetAdminParams = ctx.parameter_cursor().get()?;
+            let state = host.state_mut();
+            state.ensure_owner(&addr)?;
+            if params.is_admin {
+                state.admins.insert(params.account);
+            } else {
+                state.admins.remove(&params.account);
+            }
+            logger.log(&Event::AdminUpdated {
+                account: params.account,
+                is_admin: params.is_admin,
+            })?;
+            Ok(())
         }
         Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
 }
 
-impl<S: HasStateApi> State<S> {
-    fn empty(state_builder: &mut StateBuilder<S>) -> Self {
-        State {
-            ctr: 0,
-            games: state_builder.new_map(),
+#[derive(Serialize, SchemaType)]
+struct SetPausedParams {
+    paused: bool,
+}
+
+/// Pause or unpause the contract. While paused, no games can be created,
+/// joined or played, and no timeouts claimed, but players can still resign or
+/// abort to get their stake back, and the views keep working. The clocks of
+/// the running games start over when the contract is unpaused, so nobody
+/// loses on time because of a pause. Only the owner and the admins can do
+/// this.
+#[receive(
+    contract = "tictactoe",
+    name = "set_paused",
+    parameter = "SetPausedParams",
+    mutable,
+    enable_logger
+)]
+fn contract_set_paused<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: SetPausedParams = ctx.parameter_cursor().get()?;
+            let state = host.state_mut();
+            ensure!(state.is_admin(&addr), CustomContractError::Unauthorized);
+            if state.paused && !params.paused {
+                state.unpaused_at = ctx.metadata().slot_time();
+            }
+            state.paused = params.paused;
+            logger.log(&Event::PauseUpdated {
+                paused: params.paused,
+            })?;
+            Ok(())
         }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
+}
 
-    fn create_game(&mut self, address: AccountAddress) {
-        self.games.insert(self.ctr, Game::new(address));
-        self.ctr += 1;
-    }
+#[derive(Serialize, SchemaType)]
+struct SetTokenAllowedParams {
+    contract: ContractAddress,
+    token_id: TokenIdVec,
+    /// Whether to allow or disallow staking games in the token.
+    allowed: bool,
+}
 
-    fn join(&mut self, game_id: u64, new_player: Player) -> ContractResult<()> {
-        if let Some(the_game) = &mut self.games.get_mut(&game_id) {
-            the_game.join(new_player)
-        } else {
-            Err(CustomContractError::InvalidGameId)
+/// Allow games to be staked in a CIS-2 token through 'onReceivingCIS2', or
+/// stop allowing it. Games already staked in the token are played and paid
+/// out as usual. Only the owner and the admins can do this.
+#[receive(
+    contract = "tictactoe",
+    name = "set_token_allowed",
+    parameter = "SetTokenAllowedParams",
+    mutable,
+    enable_logger
+)]
+fn contract_set_token_allowed<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: SetTokenAllowedParams = ctx.parameter_cursor().get()?;
+            let state = host.state_mut();
+            ensure!(state.is_admin(&addr), CustomContractError::Unauthorized);
+            let token = (params.contract, params.token_id.clone());
+            if params.allowed {
+                state.allowed_tokens.insert(token);
+            } else {
+                state.allowed_tokens.remove(&token);
+            }
+            logger.log(&Event::TokenAllowed {
+                contract: params.contract,
+                token_id: params.token_id,
+                allowed: params.allowed,
+            })?;
+            Ok(())
         }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
+}
 
-    fn make_move(
-        &mut self,
-        game_id: u64,
-        player: &AccountAddress,
-        the_move: PutMove,
-    ) -> ContractResult<()> {
-        if let Some(mut the_game) = self.games.get_mut(&game_id) {
-            match the_game.game_state {
-                GameState::AwaitingOpponent => Err(CustomContractError::InvalidGameState),
-                GameState::InProgress(allowed_player) => match allowed_player {
-                    Player::Cross(addr) => {
-                        ensure!(addr == *player, CustomContractError::NotMyTurn);
-                        the_game.make_move(&allowed_player, the_move)
-                    }
-                    Player::Circle(addr) => {
-                        ensure!(addr == *player, CustomContractError::NotMyTurn);
-                        the_game.make_move(&allowed_player, the_move)
-                    }
-                },
-                GameState::Finished(_) => Err(CustomContractError::InvalidGameState),
+#[derive(Serialize, SchemaType)]
+struct UpgradeParams {
+    /// The smart contract module to upgrade to.
+    module: ModuleReference,
+    /// An entrypoint of the new module to call right after the upgrade, with
+    /// its parameter, usually 'migrate'.
+    migrate: Option<(OwnedEntrypointName, OwnedParameter)>,
+}
+
+/// Upgrade the contract to a new smart contract module, keeping its address,
+/// balance and state. Only the owner and the admins can do this. If the new
+/// module changes the layout of the state, pass its 'migrate' entrypoint to
+/// convert the state in the same transaction. The state is read as a
+/// [VersionedState], so this still works once the new module has migrated it.
+#[receive(
+    contract = "tictactoe",
+    name = "upgrade",
+    parameter = "UpgradeParams",
+    mutable,
+    enable_logger
+)]
+fn contract_upgrade<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<VersionedState<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            ensure!(
+                host.state().is_admin(&addr),
+                CustomContractError::Unauthorized
+            );
+            let params: UpgradeParams = ctx.parameter_cursor().get()?;
+            host.upgrade(params.module)?;
+            logger.log(&Event::Upgraded {
+                module: params.module,
+            })?;
+            if let Some((entrypoint, parameter)) = params.migrate {
+                host.invoke_contract_raw(
+                    &ctx.self_address(),
+                    parameter.as_parameter(),
+                    entrypoint.as_entrypoint_name(),
+                    Amount::zero(),
+                )
+                .map_err(|_| CustomContractError::FailedMigration)?;
             }
-        } else {
-            Err(CustomContractError::InvalidGameId)
+            Ok(())
         }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
     }
 }
 
-#[concordium_cfg_test]
-mod tests {
-    use super::*;
-    use test_infrastructure::*;
+/// Convert the state to the layout of this version of the contract. Called by
+/// the contract itself as part of 'upgrade', or by the owner and the admins.
+#[receive(contract = "tictactoe", name = "migrate", mutable, enable_logger)]
+fn contract_migrate<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<VersionedState<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    let authorized = match ctx.sender() {
+        Address::Account(addr) => host.state().is_admin(&addr),
+        Address::Contract(addr) => addr == ctx.self_address(),
+    };
+    ensure!(authorized, CustomContractError::Unauthorized);
+    let from_version = host.state_mut().migrate()?;
+    if from_version != STATE_VERSION {
+        logger.log(&Event::Migrated {
+            from_version,
+            to_version: STATE_VERSION,
+        })?;
+    }
+    Ok(())
+}
 
-    const INITIATOR: AccountAddress = AccountAddress([0u8; 32]);
-    const CROSS: Player = Player::Cross(AccountAddress([0u8; 32]));
-    const CIRCLE: Player = Player::Circle(AccountAddress([1u8; 32]));
+#[derive(Serialize, SchemaType)]
+struct RolesView {
+    owner: AccountAddress,
+    admins: Vec<AccountAddress>,
+    paused: bool,
+    /// The version of the layout of the state.
+    version: u32,
+}
 
-    /// Test initialization succeeds.
-    #[concordium_test]
-    fn test_game() {
-        let mut game = Game::new(INITIATOR);
-        for c in game.board.0.iter() {
-            claim_eq!(*c, Cell::Empty);
-        }
-        // The game initiator can't join his own game!
-        claim_eq!(game.join(CROSS), Err(CustomContractError::InvalidJoin));
-        // But another player can certainly join.
-        claim!(game.join(CIRCLE).is_ok());
+/// The owner and admins of the contract, and whether it is paused.
+#[receive(contract = "tictactoe", name = "roles", return_value = "RolesView")]
+fn contract_roles<S: HasStateApi>(
+    _ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<RolesView> {
+    let state = host.state();
+    Ok(RolesView {
+        owner: state.owner,
+        admins: state.admins.iter().map(|admin| *admin).collect(),
+        paused: state.paused,
+        version: state.version,
+    })
+}
 
-        // Cross starts in this game of tic tac toe!
-        claim_eq!(
-            game.make_move(&CIRCLE, PutMove::new(0)),
-            Err(CustomContractError::NotMyTurn)
+/// The shortest time a player can be given for a move. Anything shorter would
+/// let the opponent claim a timeout before a move could make it on-chain.
+const MIN_MOVE_TIMEOUT: Duration = Duration::from_millis(60_000);
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, SchemaType)]
+struct CreateGameParams {
+    move_timeout: Duration,
+    /// Make the game private to the given account.
+    opponent: Option<AccountAddress>,
+    /// The number of rows and columns of the board.
+    board_size: u8,
+    /// The number of marks in a row needed to win.
+    win_length: u8,
+    variant: GameVariant,
+    /// Commit to a secret to draw who plays first, instead of starting.
+    commitment: Option<CreatorCommitment>,
+    /// Play the moves off-chain, see [Channel].
+    channel: bool,
+}
+
+impl CreateGameParams {
+    /// Check that the move timeout is at least [MIN_MOVE_TIMEOUT], that the
+    /// board is between [MIN_BOARD_SIZE] and [MAX_BOARD_SIZE] and that a line
+    /// of 'win_length' marks fits on it. Ultimate games are only played on
+    /// the classic board, and not in a channel.
+    fn check_settings(&self) -> ContractResult<()> {
+        ensure!(
+            self.move_timeout >= MIN_MOVE_TIMEOUT,
+            CustomContractError::InvalidMoveTimeout
         );
-        // When it's a players turn, they should be able to make a move.
-        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
-        // One is not allowed to make two consecutive moves!
-        claim_eq!(
-            game.make_move(&CROSS, PutMove::new(0)),
-            Err(CustomContractError::NotMyTurn)
+        if self.variant == GameVariant::Ultimate {
+            ensure!(!self.channel, CustomContractError::InvalidBoard);
+            ensure!(
+                self.board_size == 3 && self.win_length == 3,
+                CustomContractError::InvalidBoard
+            );
+        }
+        ensure!(
+            (MIN_BOARD_SIZE..=MAX_BOARD_SIZE).contains(&self.board_size),
+            CustomContractError::InvalidBoard
         );
-        // one is not allowed to put a mark on top of each others
-        claim_eq!(
-            game.make_move(&CIRCLE, PutMove::new(0)),
-            Err(CustomContractError::InvalidMove)
+        ensure!(
+            (MIN_BOARD_SIZE..=self.board_size).contains(&self.win_length),
+            CustomContractError::InvalidBoard
         );
-        // The game continues...
-        claim!(game.make_move(&CIRCLE, PutMove::new(1)).is_ok());
-        claim!(game.make_move(&CROSS, PutMove::new(3)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
+        Ok(())
+    }
+}
 
-        // The initiator wins... must be cheat.
-        claim!(game.make_move(&CROSS, PutMove::new(6)).is_ok());
-        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+/// Create a new game. The amount sent along is the stake the opponent
+/// has to match when joining.
+#[receive(
+    contract = "tictactoe",
+    name = "create_game",
+    parameter = "CreateGameParams",
+    mutable,
+    payable,
+    enable_logger
+)]
+fn contract_create<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    amount: Amount,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: CreateGameParams = ctx.parameter_cursor().get()?;
+            params.check_settings()?;
+            host.state().check_commitment(&params)?;
+            let (state, state_builder) = host.state_and_builder();
+            let id = state.create_game(state_builder, addr, amount, &params); // this is cross.
+            logger.log(&Event::GameCreated { id, cross: addr })?;
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
 
-        // Polish this part. It is not only not Circles turn, the game is also finished!
-        claim_eq!(
-            game.make_move(&CROSS, PutMove::new(8)),
-            Err(CustomContractError::NotMyTurn)
-        );
+#[derive(Serialize, SchemaType)]
+struct JoinParams {
+    game_id: u64,
+}
 
-        // Let's play a game... Horizontally that is..
-        game = Game::new(INITIATOR);
-        claim!(game.join(CIRCLE).is_ok());
+#[derive(Serialize, SchemaType)]
+struct JoinGameParams {
+    game_id: u64,
+    /// The commitment to a secret, required if the creator committed to one.
+    commitment: Option<Commitment>,
+}
 
-        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(3)).is_ok());
+#[receive(
+    contract = "tictactoe",
+    name = "join_game",
+    parameter = "JoinGameParams",
+    mutable,
+    payable,
+    enable_logger
+)]
+fn contract_join<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    amount: Amount,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    // Parse the 'JoinGameParams'
+    let params: JoinGameParams = ctx.parameter_cursor().get()?;
+    // Get the sender of the transaction
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let now = ctx.metadata().slot_time();
+            let (state, state_builder) = host.state_and_builder();
+            state.join(
+                state_builder,
+                params.game_id,
+                Player::Circle(addr),
+                amount,
+                params.commitment,
+                now,
+            )?;
+            logger.log(&Event::PlayerJoined {
+                id: params.game_id,
+                circle: addr,
+            })?;
+            Ok(())
+        }
+        // We only allow humans to play.
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
 
-        claim!(game.make_move(&CROSS, PutMove::new(1)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
+/// What to do with CIS-2 tokens sent to 'onReceivingCIS2'. It is passed as
+/// the additional data of the transfer.
+#[derive(Serialize, SchemaType)]
+enum TokenStakeAction {
+    /// Create a game staked in the tokens.
+    CreateGame(CreateGameParams),
+    /// Join a game staked in the same amount of the same token.
+    JoinGame(JoinGameParams),
+}
 
-        claim!(game.make_move(&CROSS, PutMove::new(2)).is_ok());
-        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+/// Create or join a game staked in CIS-2 tokens. The player transfers the
+/// tokens to this entrypoint of the contract with the token contract's
+/// 'transfer', passing a [TokenStakeAction] as the additional data. The
+/// stakes are paid out through the token contract like CCD stakes. Only
+/// tokens the admins allowed with 'set_token_allowed' are accepted, as any
+/// contract can call this and claim to have transferred tokens.
+#[receive(
+    contract = "tictactoe",
+    name = "onReceivingCIS2",
+    parameter = "OnReceivingCis2Params<TokenIdVec>",
+    mutable,
+    enable_logger
+)]
+fn contract_on_receiving_cis2<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    let contract = match ctx.sender() {
+        // Trophies cannot be staked.
+        Address::Contract(contract) if contract != ctx.self_address() => contract,
+        _ => return Err(CustomContractError::NotATokenContract),
+    };
+    let params: OnReceivingCis2Params<TokenIdVec> = ctx.parameter_cursor().get()?;
+    ensure!(
+        host.state()
+            .allowed_tokens
+            .contains(&(contract, params.token_id.clone())),
+        CustomContractError::TokenNotAllowed
+    );
+    let addr = match params.from {
+        Address::Account(addr) => addr,
+        Address::Contract(_) => return Err(CustomContractError::NotAHuman),
+    };
+    host.state().ensure_not_paused()?;
+    let stake = TokenStake {
+        contract,
+        token_id: params.token_id,
+        amount: params.amount,
+    };
+    let now = ctx.metadata().slot_time();
+    let (state, state_builder) = host.state_and_builder();
+    match from_bytes(&params.data.0)? {
+        TokenStakeAction::CreateGame(create) => {
+            create.check_settings()?;
+            state.check_commitment(&create)?;
+            // The winner gets twice the stake, which has to fit in an amount.
+            ensure!(
+                stake.amount.0.checked_mul(2).is_some(),
+                CustomContractError::WrongStake
+            );
+            let id = state.create_game(state_builder, addr, Amount::zero(), &create);
+            if let Some(mut the_game) = state.games.get_mut(&id) {
+                the_game.token_stake = Some(stake);
+            }
+            logger.log(&Event::GameCreated { id, cross: addr })?;
+        }
+        TokenStakeAction::JoinGame(join) => {
+            state.join_with_tokens(
+                state_builder,
+                join.game_id,
+                Player::Circle(addr),
+                stake,
+                join.commitment,
+                now,
+            )?;
+            logger.log(&Event::PlayerJoined {
+                id: join.game_id,
+                circle: addr,
+            })?;
+        }
+    }
+    Ok(())
+}
 
-        // Let's win via the mid game'!
-        game = Game::new(INITIATOR);
-        claim!(game.join(CIRCLE).is_ok());
+#[derive(Serialize, SchemaType)]
+struct MakeMoveParams {
+    game_id: u64,
+    the_move: u64,
+}
 
-        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(1)).is_ok());
+#[receive(
+    contract = "tictactoe",
+    name = "make_move",
+    parameter = "MakeMoveParams",
+    mutable,
+    enable_logger
+)]
+fn contract_make_move<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: MakeMoveParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            move_for(host, logger, ctx.self_address(), addr, &params, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
 
-        claim!(game.make_move(&CROSS, PutMove::new(4)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(7)).is_ok());
+/// Make the move described by 'params' for the account 'addr'.
+fn move_for<S: HasStateApi>(
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    self_address: ContractAddress,
+    addr: AccountAddress,
+    params: &MakeMoveParams,
+    now: Timestamp,
+) -> ContractResult<()> {
+    host.state().ensure_not_paused()?;
+    let (state, _) = host.state_and_builder();
+    let the_move = PutMove::new(params.the_move as usize);
+    let player = state.make_move(params.game_id, &addr, the_move, now)?;
+    logger.log(&Event::MoveMade {
+        id: params.game_id,
+        player,
+        cell: params.the_move,
+    })?;
+    settle(host, logger, self_address, params.game_id, now)
+}
 
-        claim!(game.make_move(&CROSS, PutMove::new(8)).is_ok());
-        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+/// Signed permits are hashed with this prefix, so that the signature of a
+/// permit cannot be passed off as a signature of anything else.
+const PERMIT_PREFIX: &[u8] = b"tictactoe.permit_move";
 
-        // Let's now play a dull draw
-        game = Game::new(INITIATOR);
-        claim!(game.join(CIRCLE).is_ok());
-        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(1)).is_ok());
-        claim!(game.make_move(&CROSS, PutMove::new(2)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
-        claim!(game.make_move(&CROSS, PutMove::new(3)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(5)).is_ok());
-        claim!(game.make_move(&CROSS, PutMove::new(7)).is_ok());
-        claim!(game.make_move(&CIRCLE, PutMove::new(6)).is_ok());
-        claim!(game.make_move(&CROSS, PutMove::new(8)).is_ok());
-        claim_eq!(game.game_state, GameState::Finished(None));
+/// A move signed off-chain by the player.
+#[derive(Serialize, SchemaType)]
+struct PermitMessage {
+    /// The contract the permit is for.
+    contract_address: ContractAddress,
+    /// The next nonce of the signer, see the 'nonce_of' view.
+    nonce: u64,
+    /// The permit cannot be used after this time.
+    expiry: Timestamp,
+    payload: MakeMoveParams,
+}
+
+#[derive(Serialize, SchemaType)]
+struct PermitMoveParams {
+    /// The player making the move.
+    signer: AccountAddress,
+    /// The signatures of the signer on the SHA-256 hash of [PERMIT_PREFIX]
+    /// followed by the serialized 'message'.
+    signature: AccountSignatures,
+    message: PermitMessage,
+}
+
+/// Make a move signed off-chain by the player, so that anyone can submit it
+/// and pay the fee for them. Every permit carries the next nonce of the
+/// signer and can only be used once, before its expiry.
+#[receive(
+    contract = "tictactoe",
+    name = "permit_move",
+    parameter = "PermitMoveParams",
+    mutable,
+    enable_logger,
+    crypto_primitives
+)]
+fn contract_permit_move<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    let params: PermitMoveParams = ctx.parameter_cursor().get()?;
+    let message_hash =
+        crypto_primitives.hash_sha2_256(&[PERMIT_PREFIX, &to_bytes(&params.message)].concat());
+    let valid = host
+        .check_account_signature(params.signer, &params.signature, &message_hash.0)
+        .map_err(|_| CustomContractError::WrongSignature)?;
+    ensure!(valid, CustomContractError::WrongSignature);
+    let now = ctx.metadata().slot_time();
+    use_permit(
+        host,
+        logger,
+        ctx.self_address(),
+        params.signer,
+        &params.message,
+        now,
+    )
+}
+
+/// Make the move of a permit whose signature has been checked, if it is for
+/// this contract, has not expired and carries the next nonce of the signer.
+fn use_permit<S: HasStateApi>(
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    self_address: ContractAddress,
+    signer: AccountAddress,
+    message: &PermitMessage,
+    now: Timestamp,
+) -> ContractResult<()> {
+    ensure!(
+        message.contract_address == self_address,
+        CustomContractError::WrongContract
+    );
+    ensure!(message.expiry >= now, CustomContractError::PermitExpired);
+    let state = host.state_mut();
+    let nonce = state.nonce_of(&signer);
+    ensure!(message.nonce == nonce, CustomContractError::WrongNonce);
+    state.nonces.insert(signer, nonce + 1);
+    logger.log(&Event::Nonce {
+        account: signer,
+        nonce,
+    })?;
+    move_for(host, logger, self_address, signer, &message.payload, now)
+}
+
+/// The nonce the next permit of an account has to carry.
+#[receive(
+    contract = "tictactoe",
+    name = "nonce_of",
+    parameter = "AccountParams",
+    return_value = "u64"
+)]
+fn tictactoe_nonce_of<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<u64> {
+    let params: AccountParams = ctx.parameter_cursor().get()?;
+    Ok(host.state().nonce_of(&params.account))
+}
+
+#[derive(Serialize, SchemaType)]
+struct RevealParams {
+    game_id: u64,
+    /// The secret the player committed to, see [SEAT_DRAW_PREFIX].
+    secret: Secret,
+}
+
+/// Reveal the secret committed to when creating or joining the game. When
+/// the second player reveals, the seats are drawn and the game starts.
+/// A player who does not reveal before the move timeout loses the game to
+/// an opponent who did.
+#[receive(
+    contract = "tictactoe",
+    name = "reveal",
+    parameter = "RevealParams",
+    mutable,
+    enable_logger,
+    crypto_primitives
+)]
+fn contract_reveal<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: RevealParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            let hash = crypto_primitives
+                .hash_sha2_256(
+                    &[
+                        SEAT_DRAW_PREFIX,
+                        &to_bytes(&(params.game_id, addr, params.secret)),
+                    ]
+                    .concat(),
+                )
+                .0;
+            let mut the_game = host
+                .state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?;
+            the_game.reveal(&addr, params.secret, hash, now)?;
+            if let (GameState::InProgress(_), Some(circle)) = (the_game.game_state, the_game.circle)
+            {
+                logger.log(&Event::SeatsDrawn {
+                    id: params.game_id,
+                    cross: the_game.cross.address(),
+                    circle: circle.address(),
+                })?;
+            }
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Win a game because the opponent did not make their move in time. Not
+/// while the contract is paused, as the opponent cannot move then.
+#[receive(
+    contract = "tictactoe",
+    name = "claim_timeout",
+    parameter = "JoinParams",
+    mutable,
+    enable_logger
+)]
+fn contract_claim_timeout<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            let (state, _) = host.state_and_builder();
+            state.claim_timeout(params.game_id, &addr, now)?;
+            settle(host, logger, ctx.self_address(), params.game_id, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Give up a game in progress. The opponent wins.
+#[receive(
+    contract = "tictactoe",
+    name = "resign",
+    parameter = "JoinParams",
+    mutable,
+    enable_logger
+)]
+fn contract_resign<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .resign(&addr)?;
+            settle(host, logger, ctx.self_address(), params.game_id, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Withdraw a game nobody has joined yet and get the stake back.
+#[receive(
+    contract = "tictactoe",
+    name = "cancel_game",
+    parameter = "JoinParams",
+    mutable,
+    enable_logger
+)]
+fn contract_cancel<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .cancel(&addr)?;
+            settle(host, logger, ctx.self_address(), params.game_id, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Propose to stop a game in progress. The game is only aborted once the
+/// opponent accepts with 'accept_abort'.
+#[receive(
+    contract = "tictactoe",
+    name = "propose_abort",
+    parameter = "JoinParams",
+    mutable
+)]
+fn contract_propose_abort<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .propose_abort(&addr)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Accept the proposal of the opponent to abort the game. Both players get
+/// their stake back.
+#[receive(
+    contract = "tictactoe",
+    name = "accept_abort",
+    parameter = "JoinParams",
+    mutable,
+    enable_logger
+)]
+fn contract_accept_abort<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .accept_abort(&addr)?;
+            settle(host, logger, ctx.self_address(), params.game_id, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Offer the opponent to end the game in a draw. The offer stands until the
+/// opponent answers it with 'accept_draw' or 'decline_draw', or until the
+/// next move.
+#[receive(
+    contract = "tictactoe",
+    name = "offer_draw",
+    parameter = "JoinParams",
+    mutable
+)]
+fn contract_offer_draw<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .offer_draw(&addr)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Accept the draw offered by the opponent. Both players get their stake
+/// back.
+#[receive(
+    contract = "tictactoe",
+    name = "accept_draw",
+    parameter = "JoinParams",
+    mutable,
+    enable_logger
+)]
+fn contract_accept_draw<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .answer_draw(&addr, true)?;
+            settle(host, logger, ctx.self_address(), params.game_id, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Turn down the draw offered by the opponent.
+#[receive(
+    contract = "tictactoe",
+    name = "decline_draw",
+    parameter = "JoinParams",
+    mutable
+)]
+fn contract_decline_draw<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            host.state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?
+                .answer_draw(&addr, false)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Ask the opponent to let you take back the move you just made, e.g. after a
+/// misclick. The request stands until the opponent accepts it with
+/// 'accept_takeback', or until the next move.
+#[receive(
+    contract = "tictactoe",
+    name = "request_takeback",
+    parameter = "JoinParams",
+    mutable
+)]
+fn contract_request_takeback<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            host.state_mut().request_takeback(params.game_id, &addr)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Let the opponent take back their last move. It is their turn again.
+#[receive(
+    contract = "tictactoe",
+    name = "accept_takeback",
+    parameter = "JoinParams",
+    mutable,
+    enable_logger
+)]
+fn contract_accept_takeback<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: JoinParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            let taken_back = host
+                .state_mut()
+                .accept_takeback(params.game_id, &addr, now)?;
+            logger.log(&Event::MoveTakenBack {
+                id: params.game_id,
+                player: taken_back.player,
+                cell: taken_back.cell,
+            })?;
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// The most moves bots can make in one 'play_bot_turn' transaction, so that a
+/// game between two bots is played over several transactions.
+const MAX_BOT_MOVES: u32 = 10;
+
+#[derive(Serialize, SchemaType)]
+struct SetBotParams {
+    /// The contract to play for the sender, or None to play in person again.
+    bot: Option<ContractAddress>,
+}
+
+/// Let a bot contract play the games of the sender. Whenever it is the turn of
+/// the sender, 'play_bot_turn' calls the 'on_turn' entrypoint of the bot with
+/// [OnTurnParams], and the bot returns the cell to mark as a 'u64'. The sender
+/// keeps their seats, stakes and rating, and can still move in person.
+#[receive(
+    contract = "tictactoe",
+    name = "set_bot",
+    parameter = "SetBotParams",
+    mutable,
+    enable_logger
+)]
+fn contract_set_bot<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: SetBotParams = ctx.parameter_cursor().get()?;
+            let state = host.state_mut();
+            if let Some(bot) = params.bot {
+                state.bots.insert(addr, bot);
+            } else {
+                state.bots.remove(&addr);
+            }
+            logger.log(&Event::BotUpdated {
+                account: addr,
+                bot: params.bot,
+            })?;
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// The parameter the 'on_turn' entrypoint of a bot is called with.
+#[derive(Serialize, SchemaType)]
+struct OnTurnParams {
+    game_id: GameId,
+    /// The seat the bot plays.
+    player: Player,
+    /// The board, or the meta board of an ultimate game.
+    board: Board,
+    /// The sub-boards, if this is an ultimate game.
+    ultimate: Option<UltimateBoard>,
+}
+
+/// Let the bots whose turn it is in the game make their moves, until it is the
+/// turn of a human or the game is over, for at most [MAX_BOT_MOVES] moves.
+/// Returns the number of moves made.
+///
+/// Bots are driven by keepers: the contract never calls a bot on its own, not
+/// even right after the move of its opponent, so someone has to call this
+/// whenever it is the turn of a bot, typically the operator of the bot. A bot
+/// that fails or runs out of energy therefore cannot keep its opponent from
+/// moving; it simply loses on time. While the bot is called every entrypoint
+/// that changes the state fails with 'Reentrancy'.
+#[receive(
+    contract = "tictactoe",
+    name = "play_bot_turn",
+    parameter = "JoinParams",
+    return_value = "u32",
+    mutable,
+    enable_logger
+)]
+fn contract_play_bot_turn<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<u32> {
+    host.state().ensure_no_bot_call()?;
+    if let Address::Contract(_) = ctx.sender() {
+        return Err(CustomContractError::NotAHuman);
+    }
+    host.state().ensure_not_paused()?;
+    let params: JoinParams = ctx.parameter_cursor().get()?;
+    let now = ctx.metadata().slot_time();
+    let mut moves = 0;
+    while moves < MAX_BOT_MOVES {
+        let (player, bot, on_turn) = {
+            let state = host.state();
+            let the_game = state
+                .games
+                .get(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?;
+            let player = match the_game.game_state {
+                GameState::InProgress(player) => player,
+                _ => break,
+            };
+            let bot = match state.bots.get(&player.address()) {
+                Some(bot) => *bot,
+                None => break,
+            };
+            let on_turn = OnTurnParams {
+                game_id: params.game_id,
+                player,
+                board: the_game.board.clone(),
+                ultimate: the_game.ultimate.clone(),
+            };
+            (player, bot, on_turn)
+        };
+        host.state_mut().bot_call_active = true;
+        let response = host.invoke_contract(
+            &bot,
+            &on_turn,
+            EntrypointName::new_unchecked("on_turn"),
+            Amount::zero(),
+        );
+        host.state_mut().bot_call_active = false;
+        let cell = match response {
+            Ok((_, Some(mut return_value))) => u64::deserial(&mut return_value).ok(),
+            _ => None,
+        };
+        let applied = match cell {
+            Some(cell) => host
+                .state_mut()
+                .make_move(
+                    params.game_id,
+                    &player.address(),
+                    PutMove::new(cell as usize),
+                    now,
+                )
+                .map(|_| cell)
+                .ok(),
+            None => None,
+        };
+        let cell = match applied {
+            Some(cell) => cell,
+            // The moves made so far stand, and the bot that failed keeps its
+            // turn.
+            None if moves > 0 => break,
+            None => return Err(CustomContractError::BotFailed),
+        };
+        logger.log(&Event::MoveMade {
+            id: params.game_id,
+            player,
+            cell,
+        })?;
+        settle(host, logger, ctx.self_address(), params.game_id, now)?;
+        moves += 1;
+    }
+    ensure!(moves > 0, CustomContractError::NotABotTurn);
+    Ok(moves)
+}
+
+/// Transfer trophies, following the CIS-2 standard. The sender has to hold
+/// them or be an operator of their owner, and receiving contracts are
+/// notified through the entrypoint they name.
+#[receive(
+    contract = "tictactoe",
+    name = "transfer",
+    parameter = "TransferParams<TokenId>",
+    mutable,
+    enable_logger
+)]
+fn contract_transfer<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> Cis2Result<()> {
+    host.state().ensure_no_bot_call()?;
+    let TransferParams::<TokenId>(transfers) = ctx.parameter_cursor().get()?;
+    let sender = ctx.sender();
+    for Transfer {
+        token_id,
+        amount,
+        from,
+        to,
+        data,
+    } in transfers
+    {
+        let state = host.state_mut();
+        ensure!(
+            from == sender || state.is_operator(&from, &sender),
+            Cis2Error::Unauthorized
+        );
+        state.transfer_trophy(token_id, amount, &from, to.address())?;
+        logger.log(&Event::TrophyTransferred {
+            token_id,
+            amount,
+            from,
+            to: to.address(),
+        })?;
+        if let Receiver::Contract(address, entrypoint) = to {
+            let params = OnReceivingCis2Params {
+                token_id,
+                amount,
+                from,
+                data,
+            };
+            host.invoke_contract(
+                &address,
+                &params,
+                entrypoint.as_entrypoint_name(),
+                Amount::zero(),
+            )
+            .map_err(|_| CustomContractError::TransferRejected)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add or remove operators, who may transfer all trophies of the sender.
+#[receive(
+    contract = "tictactoe",
+    name = "updateOperator",
+    parameter = "UpdateOperatorParams",
+    mutable,
+    enable_logger
+)]
+fn contract_update_operator<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> Cis2Result<()> {
+    host.state().ensure_no_bot_call()?;
+    let UpdateOperatorParams(updates) = ctx.parameter_cursor().get()?;
+    let owner = ctx.sender();
+    for UpdateOperator { update, operator } in updates {
+        let operators = &mut host.state_mut().operators;
+        match update {
+            OperatorUpdate::Add => operators.insert((owner, operator)),
+            OperatorUpdate::Remove => operators.remove(&(owner, operator)),
+        };
+        logger.log(&Event::OperatorUpdated {
+            update,
+            owner,
+            operator,
+        })?;
+    }
+    Ok(())
+}
+
+/// The amounts of trophies held by the queried addresses.
+#[receive(
+    contract = "tictactoe",
+    name = "balanceOf",
+    parameter = "BalanceOfQueryParams",
+    return_value = "BalanceOfQueryResponse"
+)]
+fn tictactoe_balance_of<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> Cis2Result<BalanceOfQueryResponse> {
+    let BalanceOfQueryParams(queries) = ctx.parameter_cursor().get()?;
+    let amounts = queries
+        .iter()
+        .map(|query| host.state().balance_of(query.token_id, &query.address))
+        .collect::<ContractResult<_>>()?;
+    Ok(BalanceOfQueryResponse(amounts))
+}
+
+/// Whether the queried addresses are operators of the queried owners.
+#[receive(
+    contract = "tictactoe",
+    name = "operatorOf",
+    parameter = "OperatorOfQueryParams",
+    return_value = "OperatorOfQueryResponse"
+)]
+fn tictactoe_operator_of<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<OperatorOfQueryResponse> {
+    let OperatorOfQueryParams(queries) = ctx.parameter_cursor().get()?;
+    Ok(OperatorOfQueryResponse(
+        queries
+            .iter()
+            .map(|query| host.state().is_operator(&query.owner, &query.address))
+            .collect(),
+    ))
+}
+
+/// The metadata URLs of the queried trophies, see
+/// [State::trophy_metadata_url]. Fails while the owner has not set where the
+/// metadata is found.
+#[receive(
+    contract = "tictactoe",
+    name = "tokenMetadata",
+    parameter = "TokenMetadataQueryParams",
+    return_value = "TokenMetadataQueryResponse"
+)]
+fn tictactoe_token_metadata<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> Cis2Result<TokenMetadataQueryResponse> {
+    let TokenMetadataQueryParams(token_ids) = ctx.parameter_cursor().get()?;
+    let state = host.state();
+    let urls = token_ids
+        .iter()
+        .map(|token_id| {
+            ensure!(
+                state.trophies.get(&token_id.0).is_some(),
+                CustomContractError::InvalidTokenId
+            );
+            state
+                .trophy_metadata_url(*token_id)
+                .ok_or(CustomContractError::MetadataUrlNotSet)
+        })
+        .collect::<ContractResult<_>>()?;
+    Ok(TokenMetadataQueryResponse(urls))
+}
+
+/// The trophy won in a game: who holds it, who lost the game and the final
+/// board. The metadata of the trophy describes this.
+#[receive(
+    contract = "tictactoe",
+    name = "trophy",
+    parameter = "JoinParams",
+    return_value = "Trophy"
+)]
+fn tictactoe_trophy<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<Trophy> {
+    let params: JoinParams = ctx.parameter_cursor().get()?;
+    Ok(host
+        .state()
+        .trophies
+        .get(&params.game_id)
+        .ok_or(CustomContractError::InvalidTokenId)?
+        .clone())
+}
+
+/// Which of the queried standards the contract implements, following CIS-0.
+#[receive(
+    contract = "tictactoe",
+    name = "supports",
+    parameter = "SupportsQueryParams",
+    return_value = "SupportsQueryResponse"
+)]
+fn tictactoe_supports<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    _host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<SupportsQueryResponse> {
+    let SupportsQueryParams(queries) = ctx.parameter_cursor().get()?;
+    Ok(SupportsQueryResponse(
+        queries
+            .iter()
+            .map(|standard| {
+                if SUPPORTED_STANDARDS.contains(&standard.0.as_str()) {
+                    SupportResult::Support
+                } else {
+                    SupportResult::NoSupport
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Channel states are hashed with this prefix before they are signed.
+const CHANNEL_PREFIX: &[u8] = b"tictactoe.channel";
+
+/// A channel state signed by both players.
+#[derive(Serialize, SchemaType)]
+struct SignedChannelState {
+    state: ChannelState,
+    /// The signatures of cross on the SHA-256 hash of [CHANNEL_PREFIX]
+    /// followed by the serialized 'state'.
+    cross_signature: AccountSignatures,
+    /// The signatures of circle on the same hash.
+    circle_signature: AccountSignatures,
+}
+
+/// Check that both players of the game signed 'signed', which is for this
+/// contract.
+fn check_channel_signatures<S: HasStateApi>(
+    host: &impl HasHost<State<S>, StateApiType = S>,
+    crypto_primitives: &impl HasCryptoPrimitives,
+    self_address: ContractAddress,
+    signed: &SignedChannelState,
+) -> ContractResult<()> {
+    ensure!(
+        signed.state.contract_address == self_address,
+        CustomContractError::WrongContract
+    );
+    let the_game = host
+        .state()
+        .games
+        .get(&signed.state.game_id)
+        .ok_or(CustomContractError::InvalidGameId)?;
+    let circle = the_game
+        .circle
+        .ok_or(CustomContractError::InvalidGameState)?;
+    let hash =
+        crypto_primitives.hash_sha2_256(&[CHANNEL_PREFIX, &to_bytes(&signed.state)].concat());
+    for (player, signature) in [
+        (the_game.cross, &signed.cross_signature),
+        (circle, &signed.circle_signature),
+    ] {
+        let valid = host
+            .check_account_signature(player.address(), signature, &hash.0)
+            .map_err(|_| CustomContractError::WrongSignature)?;
+        ensure!(valid, CustomContractError::WrongSignature);
+    }
+    Ok(())
+}
+
+/// End a game played in a channel with its final position, signed by both
+/// players. Anyone can submit it.
+#[receive(
+    contract = "tictactoe",
+    name = "close_channel",
+    parameter = "SignedChannelState",
+    mutable,
+    enable_logger,
+    crypto_primitives
+)]
+fn contract_close_channel<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    let signed: SignedChannelState = ctx.parameter_cursor().get()?;
+    check_channel_signatures(host, crypto_primitives, ctx.self_address(), &signed)?;
+    let game_id = signed.state.game_id;
+    let now = ctx.metadata().slot_time();
+    host.state_mut()
+        .games
+        .get_mut(&game_id)
+        .ok_or(CustomContractError::InvalidGameId)?
+        .close_channel(signed.state, now)?;
+    settle(host, logger, ctx.self_address(), game_id, now)
+}
+
+#[derive(Serialize, SchemaType)]
+struct DisputeParams {
+    game_id: GameId,
+    /// The last state signed by both players, if they made any moves
+    /// off-chain.
+    signed: Option<SignedChannelState>,
+}
+
+/// Move a game played in a channel back on-chain because the opponent stopped
+/// cooperating. See [Channel].
+#[receive(
+    contract = "tictactoe",
+    name = "dispute",
+    parameter = "DisputeParams",
+    mutable,
+    enable_logger,
+    crypto_primitives
+)]
+fn contract_dispute<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: DisputeParams = ctx.parameter_cursor().get()?;
+            let state = match params.signed {
+                Some(signed) => {
+                    ensure!(
+                        signed.state.game_id == params.game_id,
+                        CustomContractError::InvalidChannelState
+                    );
+                    check_channel_signatures(host, crypto_primitives, ctx.self_address(), &signed)?;
+                    Some(signed.state)
+                }
+                None => None,
+            };
+            let now = ctx.metadata().slot_time();
+            let mut the_game = host
+                .state_mut()
+                .games
+                .get_mut(&params.game_id)
+                .ok_or(CustomContractError::InvalidGameId)?;
+            the_game.dispute(&addr, state, now)?;
+            let move_count = the_game
+                .channel
+                .as_ref()
+                .map_or(0, |channel| channel.move_count);
+            drop(the_game);
+            logger.log(&Event::ChannelDisputed {
+                id: params.game_id,
+                player: addr,
+                move_count,
+            })?;
+            settle(host, logger, ctx.self_address(), params.game_id, now)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+#[derive(Serialize, SchemaType)]
+struct CreateMatchParams {
+    /// The most games the match lasts.
+    best_of: u32,
+    /// How the games of the match are played.
+    settings: CreateGameParams,
+}
+
+/// Create a match of several games against the same opponent.
+#[receive(
+    contract = "tictactoe",
+    name = "create_match",
+    parameter = "CreateMatchParams",
+    mutable,
+    enable_logger
+)]
+fn contract_create_match<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: CreateMatchParams = ctx.parameter_cursor().get()?;
+            params.settings.check_settings()?;
+            ensure!(
+                params.best_of > 0 && params.settings.commitment.is_none(),
+                CustomContractError::InvalidMatch
+            );
+            let id = host.state_mut().create_match(addr, params);
+            logger.log(&Event::MatchCreated { id, creator: addr })?;
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+#[derive(Serialize, SchemaType)]
+struct MatchParams {
+    match_id: MatchId,
+}
+
+/// Join a match, which starts its first game.
+#[receive(
+    contract = "tictactoe",
+    name = "join_match",
+    parameter = "MatchParams",
+    mutable,
+    enable_logger
+)]
+fn contract_join_match<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: MatchParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            let (state, state_builder) = host.state_and_builder();
+            state
+                .matches
+                .get_mut(&params.match_id)
+                .ok_or(CustomContractError::InvalidMatchId)?
+                .join(addr)?;
+            logger.log(&Event::MatchJoined {
+                id: params.match_id,
+                opponent: addr,
+            })?;
+            let game_id = state.start_match_game(state_builder, params.match_id, now)?;
+            log_game_started(host, logger, game_id)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// The score and the games of a match.
+#[receive(
+    contract = "tictactoe",
+    name = "match_view",
+    parameter = "MatchParams",
+    return_value = "Match"
+)]
+fn tictactoe_match_view<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<Match> {
+    let params: MatchParams = ctx.parameter_cursor().get()?;
+    Ok(host
+        .state()
+        .matches
+        .get(&params.match_id)
+        .ok_or(CustomContractError::InvalidMatchId)?
+        .clone())
+}
+
+#[derive(Serialize, SchemaType)]
+struct CreateTournamentParams {
+    /// The number of players, a power of two.
+    size: u32,
+    /// The CCD each player pays to register.
+    entry_fee: Amount,
+    /// How the games of the tournament are played.
+    settings: CreateGameParams,
+}
+
+/// Create a single-elimination tournament.
+#[receive(
+    contract = "tictactoe",
+    name = "create_tournament",
+    parameter = "CreateTournamentParams",
+    mutable,
+    enable_logger
+)]
+fn contract_create_tournament<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: CreateTournamentParams = ctx.parameter_cursor().get()?;
+            params.settings.check_settings()?;
+            ensure!(
+                params.size >= 2
+                    && params.size <= MAX_TOURNAMENT_SIZE
+                    && params.size.is_power_of_two()
+                    && params.settings.commitment.is_none(),
+                CustomContractError::InvalidTournament
+            );
+            let id = host.state_mut().create_tournament(addr, params);
+            logger.log(&Event::TournamentCreated {
+                id,
+                organizer: addr,
+            })?;
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+#[derive(Serialize, SchemaType)]
+struct TournamentParams {
+    tournament_id: TournamentId,
+}
+
+/// Register for a tournament, paying the entry fee. The last player to
+/// register starts the first round.
+#[receive(
+    contract = "tictactoe",
+    name = "register",
+    parameter = "TournamentParams",
+    mutable,
+    payable,
+    enable_logger
+)]
+fn contract_register<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    amount: Amount,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            host.state().ensure_not_paused()?;
+            let params: TournamentParams = ctx.parameter_cursor().get()?;
+            let now = ctx.metadata().slot_time();
+            let (state, state_builder) = host.state_and_builder();
+            let started = state.register(state_builder, params.tournament_id, addr, amount, now)?;
+            logger.log(&Event::PlayerRegistered {
+                id: params.tournament_id,
+                player: addr,
+            })?;
+            for game_id in started {
+                log_game_started(host, logger, game_id)?;
+            }
+            Ok(())
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// Call off a tournament that is not full yet. The entry fees are refunded.
+#[receive(
+    contract = "tictactoe",
+    name = "cancel_tournament",
+    parameter = "TournamentParams",
+    mutable,
+    enable_logger
+)]
+fn contract_cancel_tournament<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+) -> ContractResult<()> {
+    host.state().ensure_no_bot_call()?;
+    match ctx.sender() {
+        Address::Account(addr) => {
+            let params: TournamentParams = ctx.parameter_cursor().get()?;
+            host.state_mut()
+                .tournaments
+                .get_mut(&params.tournament_id)
+                .ok_or(CustomContractError::InvalidTournamentId)?
+                .cancel(&addr)?;
+            settle_tournament(host, logger, params.tournament_id)
+        }
+        Address::Contract(_) => Err(CustomContractError::NotAHuman),
+    }
+}
+
+/// The players, the bracket and the games of a tournament.
+#[receive(
+    contract = "tictactoe",
+    name = "tournament_view",
+    parameter = "TournamentParams",
+    return_value = "Tournament"
+)]
+fn tictactoe_tournament_view<S: HasStateApi>(
+    ctx: &impl HasReceiveContext,
+    host: &impl HasHost<State<S>, StateApiType = S>,
+) -> ReceiveResult<Tournament> {
+    let params: TournamentParams = ctx.parameter_cursor().get()?;
+    Ok(host
+        .state()
+        .tournaments
+        .get(&params.tournament_id)
+        .ok_or(CustomContractError::InvalidTournamentId)?
+        .clone())
+}
+
+/// Announce the outcome and pay out the prizes of a tournament that is over.
+fn settle_tournament<S: HasStateApi>(
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    tournament_id: TournamentId,
+) -> ContractResult<()> {
+    let tournament = host
+        .state()
+        .tournaments
+        .get(&tournament_id)
+        .ok_or(CustomContractError::InvalidTournamentId)?
+        .clone();
+    if !matches!(
+        tournament.tournament_state,
+        TournamentState::Finished { .. } | TournamentState::Cancelled
+    ) {
+        return Ok(());
+    }
+    logger.log(&Event::TournamentFinished {
+        id: tournament_id,
+        outcome: tournament.tournament_state,
+    })?;
+    for (to, amount) in tournament.prizes() {
+        host.invoke_transfer(&to, amount)?;
+    }
+    Ok(())
+}
+
+/// Log the creation of a game started by a match or a tournament, and its
+/// opponent joining.
+fn log_game_started<S: HasStateApi>(
+    host: &impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    game_id: GameId,
+) -> ContractResult<()> {
+    let the_game = host
+        .state()
+        .games
+        .get(&game_id)
+        .ok_or(CustomContractError::InvalidGameId)?
+        .clone();
+    logger.log(&Event::GameCreated {
+        id: game_id,
+        cross: the_game.cross.address(),
+    })?;
+    if let Some(circle) = the_game.circle {
+        logger.log(&Event::PlayerJoined {
+            id: game_id,
+            circle: circle.address(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Announce the outcome and pay out the pot of the game if it is over.
+/// If the game is part of a match or a tournament, it is updated and its next
+/// games started.
+fn settle<S: HasStateApi>(
+    host: &mut impl HasHost<State<S>, StateApiType = S>,
+    logger: &mut impl HasLogger,
+    self_address: ContractAddress,
+    game_id: GameId,
+    now: Timestamp,
+) -> ContractResult<()> {
+    let the_game = host
+        .state()
+        .games
+        .get(&game_id)
+        .ok_or(CustomContractError::InvalidGameId)?
+        .clone();
+    if !the_game.is_over() {
+        return Ok(());
+    }
+    if let Some(mut the_game) = host.state_mut().games.get_mut(&game_id) {
+        the_game.last_move = now;
+    }
+    logger.log(&Event::GameFinished {
+        id: game_id,
+        outcome: the_game.game_state,
+    })?;
+    host.state_mut().record_result(&the_game);
+    if let GameState::Finished(Some(winner)) = the_game.game_state {
+        if let Some(opponent) = the_game.opponent(&winner) {
+            let owner = Address::Account(winner.address());
+            host.state_mut().trophies.insert(
+                game_id,
+                Trophy {
+                    owner,
+                    opponent: opponent.address(),
+                    board: the_game.board.clone(),
+                },
+            );
+            logger.log(&Event::TrophyMinted {
+                token_id: TokenId(game_id),
+                amount: TokenAmount(1),
+                owner,
+            })?;
+            if let Some(metadata_url) = host.state().trophy_metadata_url(TokenId(game_id)) {
+                logger.log(&Event::TokenMetadata {
+                    token_id: TokenId(game_id),
+                    metadata_url,
+                })?;
+            }
+        }
+    }
+    if let Some(match_id) = the_game.match_id {
+        let (state, state_builder) = host.state_and_builder();
+        match state.advance_match(state_builder, match_id, &the_game, now)? {
+            Some(next_game) => log_game_started(host, logger, next_game)?,
+            None => {
+                let outcome = host
+                    .state()
+                    .matches
+                    .get(&match_id)
+                    .ok_or(CustomContractError::InvalidMatchId)?
+                    .match_state;
+                logger.log(&Event::MatchFinished {
+                    id: match_id,
+                    outcome,
+                })?;
+            }
+        }
+    }
+    if let Some(tournament_id) = the_game.tournament_id {
+        let (state, state_builder) = host.state_and_builder();
+        let started =
+            state.advance_tournament(state_builder, tournament_id, game_id, &the_game, now)?;
+        for next_game in started {
+            log_game_started(host, logger, next_game)?;
+        }
+        settle_tournament(host, logger, tournament_id)?;
+    }
+    for (to, amount) in the_game.payouts() {
+        host.invoke_transfer(&to, amount)?;
+    }
+    if let Some(stake) = &the_game.token_stake {
+        let transfers = the_game
+            .token_payouts()
+            .into_iter()
+            .map(|(to, amount)| Transfer {
+                token_id: stake.token_id.clone(),
+                amount,
+                from: Address::Contract(self_address),
+                to: Receiver::Account(to),
+                data: AdditionalData(Vec::new()),
+            })
+            .collect::<Vec<_>>();
+        if !transfers.is_empty() {
+            host.invoke_contract(
+                &stake.contract,
+                &TransferParams(transfers),
+                EntrypointName::new_unchecked("transfer"),
+                Amount::zero(),
+            )
+            .map_err(|_| CustomContractError::TokenTransferFailed)?;
+        }
+    }
+    Ok(())
+}
+
+impl<S: HasStateApi> State<S> {
+    /// The amount of the trophy 'token_id' held by 'address'.
+    fn balance_of(&self, token_id: TokenId, address: &Address) -> ContractResult<TokenAmount> {
+        let trophy = self
+            .trophies
+            .get(&token_id.0)
+            .ok_or(CustomContractError::InvalidTokenId)?;
+        Ok(TokenAmount(u64::from(trophy.owner == *address)))
+    }
+
+    /// Where the metadata of the trophy 'token_id' is found: the base URL
+    /// set by the owner with the token id appended in hex, like
+    /// '<base>0500000000000000' for the trophy of game 5. None while the
+    /// owner has not set a base URL.
+    fn trophy_metadata_url(&self, token_id: TokenId) -> Option<MetadataUrl> {
+        if self.metadata_base_url.is_empty() {
+            return None;
+        }
+        let mut url = self.metadata_base_url.clone();
+        for byte in token_id.0.to_le_bytes() {
+            url.push_str(&format!("{:02x}", byte));
+        }
+        Some(MetadataUrl { url, hash: None })
+    }
+
+    /// Whether 'address' may transfer the trophies of 'owner'.
+    fn is_operator(&self, owner: &Address, address: &Address) -> bool {
+        self.operators.contains(&(*owner, *address))
+    }
+
+    /// Move 'amount' of the trophy 'token_id' from 'from' to 'to'. Moving
+    /// nothing is allowed, as long as the trophy exists.
+    fn transfer_trophy(
+        &mut self,
+        token_id: TokenId,
+        amount: TokenAmount,
+        from: &Address,
+        to: Address,
+    ) -> ContractResult<()> {
+        let mut trophy = self
+            .trophies
+            .get_mut(&token_id.0)
+            .ok_or(CustomContractError::InvalidTokenId)?;
+        match amount.0 {
+            0 => Ok(()),
+            1 if trophy.owner == *from => {
+                trophy.owner = to;
+                Ok(())
+            }
+            _ => Err(CustomContractError::InsufficientFunds),
+        }
+    }
+
+    fn empty(owner: AccountAddress, state_builder: &mut StateBuilder<S>) -> Self {
+        State {
+            version: STATE_VERSION,
+            ctr: 0,
+            games: state_builder.new_map(),
+            games_by_account: state_builder.new_map(),
+            history: state_builder.new_map(),
+            stats: state_builder.new_map(),
+            rankings: state_builder.new_set(),
+            match_ctr: 0,
+            matches: state_builder.new_map(),
+            tournament_ctr: 0,
+            tournaments: state_builder.new_map(),
+            owner,
+            admins: state_builder.new_set(),
+            paused: false,
+            unpaused_at: Timestamp::from_timestamp_millis(0),
+            bots: state_builder.new_map(),
+            bot_call_active: false,
+            nonces: state_builder.new_map(),
+            archive: state_builder.new_map(),
+            trophies: state_builder.new_map(),
+            metadata_base_url: String::new(),
+            operators: state_builder.new_set(),
+            allowed_tokens: state_builder.new_set(),
+        }
+    }
+
+    fn ensure_owner(&self, addr: &AccountAddress) -> ContractResult<()> {
+        ensure!(self.owner == *addr, CustomContractError::Unauthorized);
+        Ok(())
+    }
+
+    /// Whether 'addr' may pause and unpause the contract.
+    fn is_admin(&self, addr: &AccountAddress) -> bool {
+        self.owner == *addr || self.admins.contains(addr)
+    }
+
+    /// Reject calls made while a bot is making its move, so that it cannot
+    /// call back into the contract, directly or through another contract.
+    /// Every entrypoint that changes the state checks this first.
+    fn ensure_no_bot_call(&self) -> ContractResult<()> {
+        ensure!(!self.bot_call_active, CustomContractError::Reentrancy);
+        Ok(())
+    }
+
+    fn ensure_not_paused(&self) -> ContractResult<()> {
+        ensure!(!self.paused, CustomContractError::ContractPaused);
+        Ok(())
+    }
+
+    /// Check that the commitment of a creator drawing seats is bound to the id
+    /// the new game gets, as the creator could never reveal otherwise.
+    fn check_commitment(&self, params: &CreateGameParams) -> ContractResult<()> {
+        if let Some(creator) = params.commitment {
+            ensure!(
+                creator.game_id == self.ctr,
+                CustomContractError::WrongGameId
+            );
+        }
+        Ok(())
+    }
+
+    fn create_match(&mut self, creator: AccountAddress, params: CreateMatchParams) -> MatchId {
+        let match_id = self.match_ctr;
+        self.matches.insert(match_id, Match::new(creator, params));
+        self.match_ctr += 1;
+        match_id
+    }
+
+    /// Start the next game of the match 'match_id', with both players seated.
+    fn start_match_game(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        match_id: MatchId,
+        now: Timestamp,
+    ) -> ContractResult<GameId> {
+        let the_match = self
+            .matches
+            .get(&match_id)
+            .ok_or(CustomContractError::InvalidMatchId)?
+            .clone();
+        let (cross, circle) = the_match.next_seats()?;
+        let settings = CreateGameParams {
+            opponent: Some(circle),
+            ..the_match.settings
+        };
+        let game_id = self.create_game(state_builder, cross, Amount::zero(), &settings);
+        if let Some(mut the_game) = self.games.get_mut(&game_id) {
+            the_game.match_id = Some(match_id);
+        }
+        self.join(
+            state_builder,
+            game_id,
+            Player::Circle(circle),
+            Amount::zero(),
+            None,
+            now,
+        )?;
+        if let Some(mut the_match) = self.matches.get_mut(&match_id) {
+            the_match.games.push(game_id);
+        }
+        Ok(game_id)
+    }
+
+    /// Count the outcome of 'the_game', which is over, in its match. Returns
+    /// the next game of the match, or None if the match is over.
+    fn advance_match(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        match_id: MatchId,
+        the_game: &Game,
+        now: Timestamp,
+    ) -> ContractResult<Option<GameId>> {
+        let match_state = {
+            let mut the_match = self
+                .matches
+                .get_mut(&match_id)
+                .ok_or(CustomContractError::InvalidMatchId)?;
+            the_match.record(the_game);
+            the_match.match_state
+        };
+        if match_state == MatchState::InProgress {
+            Ok(Some(self.start_match_game(state_builder, match_id, now)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn create_tournament(
+        &mut self,
+        organizer: AccountAddress,
+        params: CreateTournamentParams,
+    ) -> TournamentId {
+        let tournament_id = self.tournament_ctr;
+        self.tournaments
+            .insert(tournament_id, Tournament::new(organizer, params));
+        self.tournament_ctr += 1;
+        tournament_id
+    }
+
+    /// Register 'player' for the tournament, and start the first round if it
+    /// is full. Returns the games started.
+    fn register(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        tournament_id: TournamentId,
+        player: AccountAddress,
+        fee: Amount,
+        now: Timestamp,
+    ) -> ContractResult<Vec<GameId>> {
+        let mut tournament = self
+            .tournaments
+            .get(&tournament_id)
+            .ok_or(CustomContractError::InvalidTournamentId)?
+            .clone();
+        if tournament.register(player, fee)? {
+            tournament.bracket = tournament.players.clone();
+            self.start_round(state_builder, tournament_id, &mut tournament, 1, now)?;
+        }
+        let started = tournament.current_games.clone();
+        self.tournaments.insert(tournament_id, tournament);
+        Ok(started)
+    }
+
+    /// Start a game between 'cross' and 'circle' in the tournament.
+    fn start_tournament_game(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        tournament_id: TournamentId,
+        tournament: &mut Tournament,
+        (cross, circle): (AccountAddress, AccountAddress),
+        now: Timestamp,
+    ) -> ContractResult<GameId> {
+        let settings = CreateGameParams {
+            opponent: Some(circle),
+            ..tournament.settings.clone()
+        };
+        let game_id = self.create_game(state_builder, cross, Amount::zero(), &settings);
+        if let Some(mut the_game) = self.games.get_mut(&game_id) {
+            the_game.tournament_id = Some(tournament_id);
+        }
+        self.join(
+            state_builder,
+            game_id,
+            Player::Circle(circle),
+            Amount::zero(),
+            None,
+            now,
+        )?;
+        tournament.games.push(game_id);
+        Ok(game_id)
+    }
+
+    /// Pair the players of the bracket and start their games.
+    fn start_round(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        tournament_id: TournamentId,
+        tournament: &mut Tournament,
+        round: u32,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        let pairings: Vec<(AccountAddress, AccountAddress)> = tournament
+            .bracket
+            .chunks(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        tournament.current_games = Vec::new();
+        for pairing in pairings {
+            let game_id =
+                self.start_tournament_game(state_builder, tournament_id, tournament, pairing, now)?;
+            tournament.current_games.push(game_id);
+        }
+        tournament.winners = vec![None; tournament.current_games.len()];
+        tournament.tournament_state = TournamentState::InProgress { round };
+        Ok(())
+    }
+
+    /// Count the outcome of 'the_game', which is over, in its tournament.
+    /// A draw is replayed with the colours swapped, and once every pairing is
+    /// decided the next round starts or the tournament finishes. Returns the
+    /// games started.
+    fn advance_tournament(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        tournament_id: TournamentId,
+        game_id: GameId,
+        the_game: &Game,
+        now: Timestamp,
+    ) -> ContractResult<Vec<GameId>> {
+        let mut tournament = self
+            .tournaments
+            .get(&tournament_id)
+            .ok_or(CustomContractError::InvalidTournamentId)?
+            .clone();
+        let round = match tournament.tournament_state {
+            TournamentState::InProgress { round } => round,
+            _ => return Err(CustomContractError::InvalidGameState),
+        };
+        let pairing = tournament
+            .current_games
+            .iter()
+            .position(|current| *current == game_id)
+            .ok_or(CustomContractError::InvalidGameState)?;
+        let mut started = Vec::new();
+        match (the_game.game_state, the_game.circle) {
+            (GameState::Finished(Some(winner)), _) => {
+                tournament.winners[pairing] = Some(winner.address())
+            }
+            (_, Some(circle)) => {
+                let seats = (circle.address(), the_game.cross.address());
+                let replay = self.start_tournament_game(
+                    state_builder,
+                    tournament_id,
+                    &mut tournament,
+                    seats,
+                    now,
+                )?;
+                tournament.current_games[pairing] = replay;
+                started.push(replay);
+            }
+            (_, None) => return Err(CustomContractError::InvalidGameState),
+        }
+        let winners: Option<Vec<AccountAddress>> = tournament.winners.iter().copied().collect();
+        match winners {
+            Some(winners) if winners.len() == 1 => {
+                let winner = winners[0];
+                let runner_up = tournament
+                    .bracket
+                    .iter()
+                    .copied()
+                    .find(|player| *player != winner)
+                    .ok_or(CustomContractError::InvalidGameState)?;
+                tournament.tournament_state = TournamentState::Finished { winner, runner_up };
+            }
+            Some(winners) => {
+                tournament.bracket = winners;
+                self.start_round(
+                    state_builder,
+                    tournament_id,
+                    &mut tournament,
+                    round + 1,
+                    now,
+                )?;
+                started.extend(tournament.current_games.iter().copied());
+            }
+            None => (),
+        }
+        self.tournaments.insert(tournament_id, tournament);
+        Ok(started)
+    }
+
+    /// Record that 'account' plays in the game 'game_id'.
+    fn index_game(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        account: AccountAddress,
+        game_id: GameId,
+    ) {
+        self.games_by_account
+            .entry(account)
+            .or_insert_with(|| state_builder.new_set())
+            .insert(game_id);
+    }
+
+    /// The ids of the games 'account' plays in, in ascending order.
+    fn games_of(&self, account: &AccountAddress) -> Vec<GameId> {
+        let mut game_ids: Vec<GameId> = match self.games_by_account.get(account) {
+            Some(games) => games.iter().map(|game_id| *game_id).collect(),
+            None => Vec::new(),
+        };
+        game_ids.sort_unstable();
+        game_ids
+    }
+
+    fn player_stats(&self, account: &AccountAddress) -> PlayerStats {
+        self.stats
+            .get(account)
+            .map(|stats| *stats)
+            .unwrap_or_default()
+    }
+
+    /// Update the statistics and ratings of both players of a finished game.
+    /// Games ending any other way do not count.
+    fn record_result(&mut self, the_game: &Game) {
+        let circle = match the_game.circle {
+            Some(circle) => circle.address(),
+            None => return,
+        };
+        let cross = the_game.cross.address();
+        // The score of the cross player, in thousandths.
+        let score = match the_game.game_state {
+            GameState::Finished(Some(Player::Cross(_))) => 1000,
+            GameState::Finished(Some(Player::Circle(_))) => 0,
+            GameState::Finished(None) => 500,
+            _ => return,
+        };
+        let mut cross_stats = self.player_stats(&cross);
+        let mut circle_stats = self.player_stats(&circle);
+        let cross_rating = cross_stats.rating;
+        cross_stats.record(score, circle_stats.rating);
+        circle_stats.record(1000 - score, cross_rating);
+        self.update_stats(cross, cross_stats);
+        self.update_stats(circle, circle_stats);
+    }
+
+    /// Store the statistics of 'account' and move it to its new place in the
+    /// leaderboard.
+    fn update_stats(&mut self, account: AccountAddress, stats: PlayerStats) {
+        if let Some(old) = self.stats.insert(account, stats) {
+            self.rankings.remove(&RankKey::new(account, &old));
+        }
+        self.rankings.insert(RankKey::new(account, &stats));
+    }
+
+    /// The 'top' highest rated accounts, best first. Ties are broken by the
+    /// number of wins. Only the first 'top' rankings are read.
+    fn leaderboard(&self, top: usize) -> Vec<LeaderboardEntry> {
+        self.rankings
+            .iter()
+            .take(top)
+            .map(|key| LeaderboardEntry {
+                account: key.account,
+                stats: self.player_stats(&key.account),
+            })
+            .collect()
+    }
+
+    fn create_game(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        address: AccountAddress,
+        stake: Amount,
+        params: &CreateGameParams,
+    ) -> GameId {
+        let game_id = self.ctr;
+        self.games
+            .insert(game_id, Game::new(address, stake, params));
+        self.index_game(state_builder, address, game_id);
+        self.ctr += 1;
+        game_id
+    }
+
+    fn join(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        game_id: u64,
+        new_player: Player,
+        stake: Amount,
+        commitment: Option<Commitment>,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        let the_game = self
+            .games
+            .get(&game_id)
+            .ok_or(CustomContractError::InvalidGameId)?;
+        // The opponent has to match the stake of the creator.
+        ensure!(
+            the_game.stake == stake && the_game.token_stake.is_none(),
+            CustomContractError::WrongStake
+        );
+        drop(the_game);
+        self.seat(state_builder, game_id, new_player, commitment, now)
+    }
+
+    /// Join a game staked in tokens with the same stake as the creator.
+    fn join_with_tokens(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        game_id: u64,
+        new_player: Player,
+        stake: TokenStake,
+        commitment: Option<Commitment>,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        let the_game = self
+            .games
+            .get(&game_id)
+            .ok_or(CustomContractError::InvalidGameId)?;
+        ensure!(
+            the_game.token_stake == Some(stake),
+            CustomContractError::WrongStake
+        );
+        drop(the_game);
+        self.seat(state_builder, game_id, new_player, commitment, now)
+    }
+
+    /// Let 'new_player' join the game once their stake has been checked.
+    fn seat(
+        &mut self,
+        state_builder: &mut StateBuilder<S>,
+        game_id: u64,
+        new_player: Player,
+        commitment: Option<Commitment>,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        if let Some(the_game) = &mut self.games.get_mut(&game_id) {
+            if let Some(draw) = the_game.seat_draw.as_mut() {
+                draw.joiner_commitment =
+                    Some(commitment.ok_or(CustomContractError::MissingCommitment)?);
+            }
+            the_game.join(new_player)?;
+            // The clock for the first move starts now.
+            the_game.last_move = now;
+        } else {
+            return Err(CustomContractError::InvalidGameId);
+        }
+        self.index_game(state_builder, new_player.address(), game_id);
+        Ok(())
+    }
+
+    fn make_move(
+        &mut self,
+        game_id: u64,
+        player: &AccountAddress,
+        the_move: PutMove,
+        now: Timestamp,
+    ) -> ContractResult<Player> {
+        if let Some(mut the_game) = self.games.get_mut(&game_id) {
+            match the_game.game_state {
+                GameState::AwaitingOpponent | GameState::AwaitingReveal => {
+                    Err(CustomContractError::InvalidGameState)
+                }
+                GameState::InProgress(allowed_player) => {
+                    ensure!(
+                        allowed_player.address() == *player,
+                        CustomContractError::NotMyTurn
+                    );
+                    the_game.check_on_chain_play(now)?;
+                    // Once the deadline has passed the opponent can claim the win instead.
+                    ensure!(
+                        now <= the_game.deadline(self.unpaused_at),
+                        CustomContractError::MoveTimedOut
+                    );
+                    let cell = the_move.0 as u64;
+                    the_game.make_move(&allowed_player, the_move)?;
+                    the_game.last_move = now;
+                    self.history.insert(
+                        (game_id, the_game.history_len),
+                        MoveRecord {
+                            player: allowed_player,
+                            cell,
+                            made_at: now,
+                        },
+                    );
+                    the_game.history_len += 1;
+                    if let Some(channel) = the_game.channel.as_mut() {
+                        channel.move_count += 1;
+                    }
+                    Ok(allowed_player)
+                }
+                GameState::Finished(_) | GameState::Cancelled | GameState::Aborted => {
+                    Err(CustomContractError::InvalidGameState)
+                }
+            }
+        } else {
+            Err(CustomContractError::InvalidGameId)
+        }
+    }
+
+    /// The last move of the game and the one before it, if there are any.
+    fn last_moves(
+        &self,
+        game_id: GameId,
+        history_len: u32,
+    ) -> (Option<MoveRecord>, Option<MoveRecord>) {
+        let record = |back: u32| {
+            history_len
+                .checked_sub(back)
+                .and_then(|number| self.history.get(&(game_id, number)))
+                .map(|record| record.clone())
+        };
+        (record(1), record(2))
+    }
+
+    fn request_takeback(&mut self, game_id: GameId, addr: &AccountAddress) -> ContractResult<()> {
+        let history_len = self.game(game_id)?.history_len;
+        let (last, _) = self.last_moves(game_id, history_len);
+        self.games
+            .get_mut(&game_id)
+            .ok_or(CustomContractError::InvalidGameId)?
+            .request_takeback(addr, last.as_ref())
+    }
+
+    fn accept_takeback(
+        &mut self,
+        game_id: GameId,
+        addr: &AccountAddress,
+        now: Timestamp,
+    ) -> ContractResult<MoveRecord> {
+        let history_len = self.game(game_id)?.history_len;
+        let (last, previous) = self.last_moves(game_id, history_len);
+        let taken_back = self
+            .games
+            .get_mut(&game_id)
+            .ok_or(CustomContractError::InvalidGameId)?
+            .accept_takeback(addr, last.as_ref(), previous.as_ref(), now)?;
+        self.history.remove(&(game_id, history_len - 1));
+        Ok(taken_back)
+    }
+
+    fn history_page(&self, params: &HistoryParams) -> ContractResult<HistoryPage> {
+        let history_len = self.game(params.game_id)?.history_len;
+        let start = params.start.min(history_len);
+        let end = history_len.min(start.saturating_add(params.limit.min(MAX_PAGE_SIZE)));
+        let moves = (start..end)
+            .filter_map(|number| self.history.get(&(params.game_id, number)))
+            .map(|record| record.clone())
+            .collect();
+        Ok(HistoryPage {
+            moves,
+            next: if end < history_len { Some(end) } else { None },
+        })
+    }
+
+    fn list_games(&self, params: &ListGamesParams) -> GamePage {
+        let limit = params.limit.min(MAX_PAGE_SIZE) as usize;
+        let end = self.ctr.min(params.start.saturating_add(MAX_PAGE_SCAN));
+        let mut games = Vec::new();
+        let mut game_id = params.start;
+        while game_id < end && games.len() < limit {
+            let listing = match self.games.get(&game_id) {
+                Some(the_game) => Some(the_game.listing(game_id)),
+                None => self
+                    .archive
+                    .get(&game_id)
+                    .map(|summary| summary.listing(game_id)),
+            };
+            if let Some(listing) = listing {
+                if params.matches(&listing) {
+                    games.push(listing);
+                }
+            }
+            game_id += 1;
+        }
+        GamePage {
+            games,
+            next: if game_id < self.ctr {
+                Some(game_id)
+            } else {
+                None
+            },
+        }
+    }
+
+    fn nonce_of(&self, account: &AccountAddress) -> u64 {
+        self.nonces.get(account).map(|nonce| *nonce).unwrap_or(0)
+    }
+
+    /// The game 'game_id' as stored in full. Archived games fail with
+    /// 'GameArchived', only their summary is kept.
+    fn game(&self, game_id: GameId) -> ContractResult<StateRef<'_, Game>> {
+        match self.games.get(&game_id) {
+            Some(the_game) => Ok(the_game),
+            None if self.archive.get(&game_id).is_some() => Err(CustomContractError::GameArchived),
+            None => Err(CustomContractError::InvalidGameId),
+        }
+    }
+
+    /// The summary of a game, whether it is stored in full or archived.
+    fn summary(&self, game_id: GameId) -> ContractResult<GameSummary> {
+        match self.games.get(&game_id) {
+            Some(the_game) => Ok(the_game.summary()),
+            None => self
+                .archive
+                .get(&game_id)
+                .map(|summary| summary.clone())
+                .ok_or(CustomContractError::InvalidGameId),
+        }
+    }
+
+    /// Archive the games from 'start' that were over by 'cutoff'.
+    fn prune_finished(&mut self, start: GameId, cutoff: Timestamp) -> PruneResult {
+        let end = self.ctr.min(start.saturating_add(MAX_PAGE_SCAN));
+        let mut pruned = 0;
+        for game_id in start..end {
+            let (summary, history_len) = match self.games.get(&game_id) {
+                Some(the_game) if the_game.is_over() && the_game.last_move <= cutoff => {
+                    (the_game.summary(), the_game.history_len)
+                }
+                _ => continue,
+            };
+            self.games.remove(&game_id);
+            for number in 0..history_len {
+                self.history.remove(&(game_id, number));
+            }
+            self.archive.insert(game_id, summary);
+            pruned += 1;
+        }
+        PruneResult {
+            pruned,
+            next: if end < self.ctr { Some(end) } else { None },
+        }
+    }
+
+    fn claim_timeout(
+        &mut self,
+        game_id: u64,
+        claimant: &AccountAddress,
+        now: Timestamp,
+    ) -> ContractResult<()> {
+        if let Some(mut the_game) = self.games.get_mut(&game_id) {
+            the_game.claim_timeout(claimant, now, self.unpaused_at)
+        } else {
+            Err(CustomContractError::InvalidGameId)
+        }
+    }
+}
+
+#[concordium_cfg_test]
+mod tests {
+    use super::*;
+    use test_infrastructure::*;
+
+    const INITIATOR: AccountAddress = AccountAddress([0u8; 32]);
+    const OPPONENT: AccountAddress = AccountAddress([1u8; 32]);
+    const CROSS: Player = Player::Cross(AccountAddress([0u8; 32]));
+    const CIRCLE: Player = Player::Circle(AccountAddress([1u8; 32]));
+    const SELF_ADDRESS: ContractAddress = ContractAddress {
+        index: 0,
+        subindex: 0,
+    };
+
+    fn params() -> CreateGameParams {
+        CreateGameParams {
+            move_timeout: Duration::from_seconds(60),
+            opponent: None,
+            board_size: 3,
+            win_length: 3,
+            variant: GameVariant::Classic,
+            commitment: None,
+            channel: false,
+        }
+    }
+
+    /// Test initialization succeeds.
+    #[concordium_test]
+    fn test_game() {
+        let mut game = Game::new(INITIATOR, Amount::zero(), &params());
+        for i in 0..9 {
+            claim_eq!(game.board.get(i), Some(Cell::Empty));
+        }
+        // The game initiator can't join his own game!
+        claim_eq!(game.join(CROSS), Err(CustomContractError::InvalidJoin));
+        // But another player can certainly join.
+        claim!(game.join(CIRCLE).is_ok());
+
+        // Cross starts in this game of tic tac toe!
+        claim_eq!(
+            game.make_move(&CIRCLE, PutMove::new(0)),
+            Err(CustomContractError::NotMyTurn)
+        );
+        // When it's a players turn, they should be able to make a move.
+        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
+        // One is not allowed to make two consecutive moves!
+        claim_eq!(
+            game.make_move(&CROSS, PutMove::new(0)),
+            Err(CustomContractError::NotMyTurn)
+        );
+        // one is not allowed to put a mark on top of each others
+        claim_eq!(
+            game.make_move(&CIRCLE, PutMove::new(0)),
+            Err(CustomContractError::InvalidMove)
+        );
+        // The game continues...
+        claim!(game.make_move(&CIRCLE, PutMove::new(1)).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(3)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
+
+        // The initiator wins... must be cheat.
+        claim!(game.make_move(&CROSS, PutMove::new(6)).is_ok());
+        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+
+        // Polish this part. It is not only not Circles turn, the game is also finished!
+        claim_eq!(
+            game.make_move(&CROSS, PutMove::new(8)),
+            Err(CustomContractError::NotMyTurn)
+        );
+
+        // Let's play a game... Horizontally that is..
+        game = Game::new(INITIATOR, Amount::zero(), &params());
+        claim!(game.join(CIRCLE).is_ok());
+
+        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(3)).is_ok());
+
+        claim!(game.make_move(&CROSS, PutMove::new(1)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
+
+        claim!(game.make_move(&CROSS, PutMove::new(2)).is_ok());
+        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+
+        // Let's win via the mid game'!
+        game = Game::new(INITIATOR, Amount::zero(), &params());
+        claim!(game.join(CIRCLE).is_ok());
+
+        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(1)).is_ok());
+
+        claim!(game.make_move(&CROSS, PutMove::new(4)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(7)).is_ok());
+
+        claim!(game.make_move(&CROSS, PutMove::new(8)).is_ok());
+        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+
+        // Let's now play a dull draw
+        game = Game::new(INITIATOR, Amount::zero(), &params());
+        claim!(game.join(CIRCLE).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(1)).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(2)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(3)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(5)).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(7)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(6)).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(8)).is_ok());
+        claim_eq!(game.game_state, GameState::Finished(None));
+    }
+
+    /// Test that the opponent has to match the stake and that the winner
+    /// takes the pot.
+    #[concordium_test]
+    fn test_stakes() {
+        let stake = Amount::from_ccd(10);
+        let join_params = to_bytes(&JoinGameParams {
+            game_id: 0,
+            commitment: None,
+        });
+        let mut ctx = TestReceiveContext::empty();
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let state = State::empty(INITIATOR, &mut state_builder);
+        let mut host = TestHost::new(state, state_builder);
+
+        let create_params = to_bytes(&params());
+        ctx.set_parameter(&create_params);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_create(&ctx, &mut host, stake, &mut logger).is_ok());
+
+        ctx.set_parameter(&join_params);
+        ctx.set_metadata_slot_time(Timestamp::from_timestamp_millis(0));
+
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_join(&ctx, &mut host, Amount::from_ccd(1), &mut logger),
+            Err(CustomContractError::WrongStake)
+        );
+        claim!(contract_join(&ctx, &mut host, stake, &mut logger).is_ok());
+        host.set_self_balance(stake + stake);
+
+        let now = Timestamp::from_timestamp_millis(0);
+        let state = host.state_mut();
+        claim!(state.make_move(0, &INITIATOR, PutMove::new(0), now).is_ok());
+        claim!(state.make_move(0, &OPPONENT, PutMove::new(3), now).is_ok());
+        claim!(state.make_move(0, &INITIATOR, PutMove::new(1), now).is_ok());
+        claim!(state.make_move(0, &OPPONENT, PutMove::new(4), now).is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, now).is_ok());
+        claim!(host.get_transfers().is_empty());
+
+        claim!(host
+            .state_mut()
+            .make_move(0, &INITIATOR, PutMove::new(2), now)
+            .is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, now).is_ok());
+        claim_eq!(host.get_transfers(), [(INITIATOR, stake + stake)]);
+        claim_eq!(
+            logger.logs,
+            [
+                to_bytes(&Event::GameCreated {
+                    id: 0,
+                    cross: INITIATOR,
+                }),
+                to_bytes(&Event::PlayerJoined {
+                    id: 0,
+                    circle: OPPONENT,
+                }),
+                to_bytes(&Event::GameFinished {
+                    id: 0,
+                    outcome: GameState::Finished(Some(CROSS)),
+                }),
+                // No metadata URL is set, so none is logged.
+                to_bytes(&Event::TrophyMinted {
+                    token_id: TokenId(0),
+                    amount: TokenAmount(1),
+                    owner: Address::Account(INITIATOR),
+                }),
+            ]
+        );
+    }
+
+    /// Test that a stalled game can be won by the player waiting for a move.
+    #[concordium_test]
+    fn test_timeout() {
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let start = Timestamp::from_timestamp_millis(1000);
+        let late = start.checked_add(Duration::from_seconds(61)).unwrap();
+
+        // A game without time to move could be claimed right after joining.
+        claim_eq!(
+            CreateGameParams {
+                move_timeout: Duration::from_millis(0),
+                ..params()
+            }
+            .check_settings(),
+            Err(CustomContractError::InvalidMoveTimeout)
+        );
+        // Failing to log is still reported as such.
+        claim_eq!(
+            CustomContractError::from(LogError::Full),
+            CustomContractError::LogError
+        );
+
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        claim!(state
+            .join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, start)
+            .is_ok());
+
+        // It is cross' turn, so only circle can claim, and only after the deadline.
+        claim_eq!(
+            state.claim_timeout(0, &OPPONENT, start),
+            Err(CustomContractError::DeadlineNotPassed)
+        );
+        claim_eq!(
+            state.claim_timeout(0, &INITIATOR, late),
+            Err(CustomContractError::InvalidTimeoutClaim)
+        );
+        claim_eq!(
+            state.make_move(0, &INITIATOR, PutMove::new(0), late),
+            Err(CustomContractError::MoveTimedOut)
+        );
+        claim!(state.claim_timeout(0, &OPPONENT, late).is_ok());
+        claim_eq!(
+            state.games.get(&0).unwrap().game_state,
+            GameState::Finished(Some(CIRCLE))
+        );
+    }
+
+    /// Test resigning, cancelling and aborting games.
+    #[concordium_test]
+    fn test_early_endings() {
+        // Only the creator can cancel, and only while nobody has joined.
+        let mut game = Game::new(INITIATOR, Amount::from_ccd(1), &params());
+        claim_eq!(
+            game.cancel(&OPPONENT),
+            Err(CustomContractError::NotTheCreator)
+        );
+        claim!(game.cancel(&INITIATOR).is_ok());
+        claim_eq!(game.game_state, GameState::Cancelled);
+        claim_eq!(game.payouts(), [(INITIATOR, Amount::from_ccd(1))]);
+        claim_eq!(game.join(CIRCLE), Err(CustomContractError::InvalidJoin));
+
+        // Resigning lets the opponent win.
+        game = Game::new(INITIATOR, Amount::zero(), &params());
+        claim_eq!(
+            game.resign(&INITIATOR),
+            Err(CustomContractError::CannotResign)
+        );
+        claim!(game.join(CIRCLE).is_ok());
+        claim_eq!(
+            game.cancel(&INITIATOR),
+            Err(CustomContractError::CannotCancel)
+        );
+        claim!(game.resign(&INITIATOR).is_ok());
+        claim_eq!(game.game_state, GameState::Finished(Some(CIRCLE)));
+
+        // Aborting takes both players, and a move turns the proposal down.
+        game = Game::new(INITIATOR, Amount::from_ccd(1), &params());
+        claim!(game.join(CIRCLE).is_ok());
+        claim_eq!(
+            game.accept_abort(&OPPONENT),
+            Err(CustomContractError::NoAbortProposal)
+        );
+        claim!(game.propose_abort(&INITIATOR).is_ok());
+        claim_eq!(
+            game.propose_abort(&OPPONENT),
+            Err(CustomContractError::AbortAlreadyProposed)
+        );
+        claim_eq!(
+            game.accept_abort(&INITIATOR),
+            Err(CustomContractError::NoAbortProposal)
+        );
+        claim!(game.make_move(&CROSS, PutMove::new(0)).is_ok());
+        claim_eq!(
+            game.accept_abort(&OPPONENT),
+            Err(CustomContractError::NoAbortProposal)
+        );
+        claim!(game.propose_abort(&OPPONENT).is_ok());
+        claim!(game.accept_abort(&INITIATOR).is_ok());
+        claim_eq!(game.game_state, GameState::Aborted);
+        claim_eq!(
+            game.payouts(),
+            [
+                (INITIATOR, Amount::from_ccd(1)),
+                (OPPONENT, Amount::from_ccd(1))
+            ]
+        );
+    }
+
+    /// Test that only the invited player can join a private game.
+    #[concordium_test]
+    fn test_private_game() {
+        let invited = AccountAddress([2u8; 32]);
+        let mut game = Game::new(
+            INITIATOR,
+            Amount::zero(),
+            &CreateGameParams {
+                opponent: Some(invited),
+                ..params()
+            },
+        );
+        claim_eq!(game.join(CIRCLE), Err(CustomContractError::NotInvited));
+        claim!(game.join(Player::Circle(invited)).is_ok());
+    }
+
+    /// Test paging through the games with and without filters.
+    #[concordium_test]
+    fn test_list_games() {
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let now = Timestamp::from_timestamp_millis(0);
+        for _ in 0..5 {
+            state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        }
+        claim!(state
+            .join(&mut state_builder, 1, CIRCLE, Amount::zero(), None, now)
+            .is_ok());
+        claim!(state
+            .join(&mut state_builder, 3, CIRCLE, Amount::zero(), None, now)
+            .is_ok());
+
+        let mut list = ListGamesParams {
+            start: 0,
+            limit: 2,
+            state: None,
+            account: None,
+        };
+        let page = state.list_games(&list);
+        claim_eq!(page.games.len(), 2);
+        claim_eq!(page.next, Some(2));
+        list.start = 4;
+        let page = state.list_games(&list);
+        claim_eq!(page.games.len(), 1);
+        claim_eq!(page.next, None);
+
+        list.start = 0;
+        list.account = Some(OPPONENT);
+        let page = state.list_games(&list);
+        claim_eq!(page.games.iter().map(|g| g.id).collect::<Vec<_>>(), [1, 3]);
+        claim_eq!(page.next, Some(4));
+
+        claim_eq!(state.games_of(&OPPONENT), [1, 3]);
+        claim_eq!(state.games_of(&INITIATOR), [0, 1, 2, 3, 4]);
+        claim!(state.games_of(&AccountAddress([2u8; 32])).is_empty());
+
+        list.account = None;
+        list.limit = 10;
+        list.state = Some(GameStateKind::AwaitingOpponent);
+        let page = state.list_games(&list);
+        claim_eq!(
+            page.games.iter().map(|g| g.id).collect::<Vec<_>>(),
+            [0, 2, 4]
+        );
+        claim_eq!(page.next, None);
+    }
+
+    /// Test that finished games update the statistics and ratings.
+    #[concordium_test]
+    fn test_player_stats() {
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let mut the_game = Game::new(INITIATOR, Amount::zero(), &params());
+        the_game.circle = Some(CIRCLE);
+
+        the_game.game_state = GameState::Aborted;
+        state.record_result(&the_game);
+        claim!(state.leaderboard(10).is_empty());
+
+        the_game.game_state = GameState::Finished(Some(CROSS));
+        state.record_result(&the_game);
+        claim_eq!(
+            state.player_stats(&INITIATOR),
+            PlayerStats {
+                wins: 1,
+                losses: 0,
+                draws: 0,
+                streak: 1,
+                rating: 1216,
+            }
+        );
+        claim_eq!(state.player_stats(&OPPONENT).streak, -1);
+        claim_eq!(state.player_stats(&OPPONENT).rating, 1184);
+
+        the_game.game_state = GameState::Finished(None);
+        state.record_result(&the_game);
+        let stats = state.player_stats(&INITIATOR);
+        claim_eq!((stats.draws, stats.streak, stats.rating), (1, 0, 1215));
+        claim_eq!(state.player_stats(&OPPONENT).rating, 1185);
+
+        let leaders = state.leaderboard(1);
+        claim_eq!(leaders.len(), 1);
+        claim_eq!(leaders[0].account, INITIATOR);
+
+        // The opponent wins the rematch, and then loses to a newcomer who
+        // goes straight to the top.
+        let third = AccountAddress([2u8; 32]);
+        the_game.game_state = GameState::Finished(Some(CIRCLE));
+        state.record_result(&the_game);
+        let mut newcomer = Game::new(third, Amount::zero(), &params());
+        newcomer.circle = Some(CIRCLE);
+        newcomer.game_state = GameState::Finished(Some(Player::Cross(third)));
+        state.record_result(&newcomer);
+        let leaders: Vec<AccountAddress> = state
+            .leaderboard(10)
+            .iter()
+            .map(|entry| entry.account)
+            .collect();
+        claim_eq!(leaders, [third, INITIATOR, OPPONENT]);
+        claim_eq!(
+            state.leaderboard(10)[2].stats,
+            state.player_stats(&OPPONENT)
+        );
+        claim_eq!(
+            state.player_stats(&AccountAddress([3u8; 32])),
+            PlayerStats::default()
+        );
+
+        claim_eq!(expected_score(1200, 1200), 500);
+        claim_eq!(expected_score(2000, 1200), 909);
+        claim_eq!(expected_score(1200, 1250), 1000 - 571);
+    }
+
+    /// Test a game of five in a row on a 15×15 board.
+    #[concordium_test]
+    fn test_gomoku() {
+        let gomoku = CreateGameParams {
+            board_size: 15,
+            win_length: 5,
+            ..params()
+        };
+        claim!(gomoku.check_settings().is_ok());
+        claim_eq!(
+            CreateGameParams {
+                win_length: 16,
+                ..gomoku
+            }
+            .check_settings(),
+            Err(CustomContractError::InvalidBoard)
+        );
+        claim_eq!(
+            CreateGameParams {
+                board_size: 20,
+                ..params()
+            }
+            .check_settings(),
+            Err(CustomContractError::InvalidBoard)
+        );
+
+        let mut game = Game::new(INITIATOR, Amount::zero(), &gomoku);
+        // The 225 cells are packed four to a byte.
+        claim_eq!(game.board.cells.len(), 57);
+        claim!(game.join(CIRCLE).is_ok());
+        claim_eq!(
+            game.make_move(&CROSS, PutMove::new(225)),
+            Err(CustomContractError::InvalidMove)
+        );
+        // Cross builds a diagonal from the top right corner, while circle
+        // fills the first row.
+        for i in 0..4 {
+            claim!(game.make_move(&CROSS, PutMove::new(14 + 14 * i)).is_ok());
+            claim!(game.make_move(&CIRCLE, PutMove::new(i)).is_ok());
+        }
+        claim_eq!(game.game_state, GameState::InProgress(CROSS));
+        claim!(game.make_move(&CROSS, PutMove::new(70)).is_ok());
+        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+    }
+
+    /// Test that moves in an ultimate game are sent to the sub-board matching
+    /// the previous move, and that winning a sub-board marks the meta board.
+    #[concordium_test]
+    fn test_ultimate() {
+        let ultimate = CreateGameParams {
+            variant: GameVariant::Ultimate,
+            ..params()
+        };
+        claim!(ultimate.check_settings().is_ok());
+        claim_eq!(
+            CreateGameParams {
+                board_size: 15,
+                win_length: 5,
+                ..ultimate
+            }
+            .check_settings(),
+            Err(CustomContractError::InvalidBoard)
+        );
+
+        let mut game = Game::new(INITIATOR, Amount::zero(), &ultimate);
+        claim!(game.join(CIRCLE).is_ok());
+        claim_eq!(
+            game.make_move(&CROSS, PutMove::new(81)),
+            Err(CustomContractError::InvalidMove)
+        );
+        // Cross plays the top left cell of the center sub-board, so circle
+        // has to play in the top left sub-board.
+        claim!(game.make_move(&CROSS, PutMove::new(36)).is_ok());
+        claim_eq!(
+            game.make_move(&CIRCLE, PutMove::new(40)),
+            Err(CustomContractError::WrongSubBoard)
+        );
+        claim!(game.make_move(&CIRCLE, PutMove::new(4)).is_ok());
+        claim!(game.make_move(&CROSS, PutMove::new(37)).is_ok());
+        claim!(game.make_move(&CIRCLE, PutMove::new(13)).is_ok());
+        // Cross wins the center sub-board with the top row.
+        claim!(game.make_move(&CROSS, PutMove::new(38)).is_ok());
+        claim_eq!(game.board.get(4), Some(Cell::Cross));
+        claim_eq!(game.game_state, GameState::InProgress(CIRCLE));
+        // Circle is sent to the center sub-board, which is closed, so cross
+        // can play anywhere that is open.
+        claim!(game.make_move(&CIRCLE, PutMove::new(22)).is_ok());
+        claim_eq!(game.ultimate.as_ref().unwrap().next_sub_board, None);
+        claim_eq!(
+            game.make_move(&CROSS, PutMove::new(39)),
+            Err(CustomContractError::InvalidMove)
+        );
+        claim!(game.make_move(&CROSS, PutMove::new(80)).is_ok());
+        claim_eq!(game.ultimate.unwrap().next_sub_board, Some(8));
+    }
+
+    /// Test drawing the seats by revealing committed secrets, and that a
+    /// player who does not reveal loses.
+    #[concordium_test]
+    fn test_seat_draw() {
+        let creator_secret = [1u8; 32];
+        let mut joiner_secret = [1u8; 32];
+        joiner_secret[31] = 3;
+        // The hashes don't matter here, 'reveal' is given the hash of the secret.
+        let committed = CreateGameParams {
+            commitment: Some(CreatorCommitment {
+                game_id: 0,
+                commitment: [10u8; 32],
+            }),
+            ..params()
+        };
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let now = Timestamp::from_timestamp_millis(0);
+        let late = now.checked_add(Duration::from_seconds(61)).unwrap();
+
+        claim!(state.check_commitment(&committed).is_ok());
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &committed);
+        // The commitment is bound to game 0, so it cannot create game 1.
+        claim_eq!(
+            state.check_commitment(&committed),
+            Err(CustomContractError::WrongGameId)
+        );
+        claim_eq!(
+            state.join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, now),
+            Err(CustomContractError::MissingCommitment)
+        );
+        claim!(state
+            .join(
+                &mut state_builder,
+                0,
+                CIRCLE,
+                Amount::zero(),
+                Some([20u8; 32]),
+                now
+            )
+            .is_ok());
+
+        let mut game = state.games.get(&0).unwrap().clone();
+        claim_eq!(game.game_state, GameState::AwaitingReveal);
+        claim_eq!(
+            game.make_move(&CROSS, PutMove::new(0)),
+            Err(CustomContractError::NotMyTurn)
+        );
+        claim_eq!(
+            game.reveal(&INITIATOR, creator_secret, [20u8; 32], now),
+            Err(CustomContractError::WrongSecret)
+        );
+        claim!(game
+            .reveal(&INITIATOR, creator_secret, [10u8; 32], now)
+            .is_ok());
+        claim_eq!(
+            game.reveal(&INITIATOR, creator_secret, [10u8; 32], now),
+            Err(CustomContractError::AlreadyRevealed)
+        );
+
+        // The opponent never reveals, so the creator wins once the timeout passed.
+        let mut stalled = game.clone();
+        claim_eq!(
+            stalled.claim_timeout(&OPPONENT, late, now),
+            Err(CustomContractError::InvalidTimeoutClaim)
+        );
+        claim_eq!(
+            stalled.claim_timeout(&INITIATOR, now, now),
+            Err(CustomContractError::DeadlineNotPassed)
+        );
+        claim!(stalled.claim_timeout(&INITIATOR, late, now).is_ok());
+        claim_eq!(stalled.game_state, GameState::Finished(Some(CROSS)));
+
+        // The secrets differ in a single bit, so the joiner plays cross and
+        // starts.
+        claim!(game
+            .reveal(&OPPONENT, joiner_secret, [20u8; 32], late)
+            .is_ok());
+        claim_eq!(game.cross, Player::Cross(OPPONENT));
+        claim_eq!(game.circle, Some(Player::Circle(INITIATOR)));
+        claim_eq!(
+            game.game_state,
+            GameState::InProgress(Player::Cross(OPPONENT))
+        );
+        claim_eq!(game.last_move, late);
+    }
+
+    /// Test that a joiner copying the commitment of the creator cannot reveal
+    /// the secret of the creator as their own.
+    #[concordium_test]
+    fn test_copied_commitment() {
+        let now = Timestamp::from_timestamp_millis(0);
+        let secret = [7u8; 32];
+        // Fold the message into 32 bytes, which is enough to tell apart the
+        // messages of the two players.
+        let crypto_primitives = TestCryptoPrimitives::new();
+        crypto_primitives.setup_hash_sha2_256_mock(|data| {
+            let mut hash = [0u8; 32];
+            for (i, byte) in data.iter().enumerate() {
+                hash[i % 32] ^= byte;
+            }
+            HashSha2256(hash)
+        });
+        let commitment = crypto_primitives
+            .hash_sha2_256(&[SEAT_DRAW_PREFIX, &to_bytes(&(0u64, INITIATOR, secret))].concat())
+            .0;
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let committed = CreateGameParams {
+            commitment: Some(CreatorCommitment {
+                game_id: 0,
+                commitment,
+            }),
+            ..params()
+        };
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &committed);
+        claim!(state
+            .join(
+                &mut state_builder,
+                0,
+                CIRCLE,
+                Amount::zero(),
+                Some(commitment),
+                now
+            )
+            .is_ok());
+        let mut host = TestHost::new(state, state_builder);
+        let mut ctx = TestReceiveContext::empty();
+        let mut logger = TestLogger::init();
+        ctx.set_metadata_slot_time(now);
+        let reveal = to_bytes(&RevealParams { game_id: 0, secret });
+        ctx.set_parameter(&reveal);
+
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_reveal(&ctx, &mut host, &mut logger, &crypto_primitives).is_ok());
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_reveal(&ctx, &mut host, &mut logger, &crypto_primitives),
+            Err(CustomContractError::WrongSecret)
+        );
+    }
+
+    /// Test that the games of a match start one after the other with the
+    /// colours swapped, until one player has won a majority.
+    #[concordium_test]
+    fn test_match() {
+        let now = Timestamp::from_timestamp_millis(0);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let state = State::empty(INITIATOR, &mut state_builder);
+        let mut host = TestHost::new(state, state_builder);
+
+        let no_games = to_bytes(&CreateMatchParams {
+            best_of: 0,
+            settings: params(),
+        });
+        ctx.set_sender(Address::Account(INITIATOR));
+        ctx.set_parameter(&no_games);
+        claim_eq!(
+            contract_create_match(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::InvalidMatch)
+        );
+        let best_of_three = to_bytes(&CreateMatchParams {
+            best_of: 3,
+            settings: params(),
+        });
+        ctx.set_parameter(&best_of_three);
+        claim!(contract_create_match(&ctx, &mut host, &mut logger).is_ok());
+
+        let join_params = to_bytes(&MatchParams { match_id: 0 });
+        ctx.set_parameter(&join_params);
+        claim_eq!(
+            contract_join_match(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::InvalidJoin)
+        );
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim!(contract_join_match(&ctx, &mut host, &mut logger).is_ok());
+
+        // The creator wins the first game as cross.
+        let state = host.state_mut();
+        claim_eq!(
+            state.games.get(&0).unwrap().game_state,
+            GameState::InProgress(CROSS)
+        );
+        for (player, cell) in [
+            (INITIATOR, 0),
+            (OPPONENT, 3),
+            (INITIATOR, 1),
+            (OPPONENT, 4),
+            (INITIATOR, 2),
+        ] {
+            claim!(state.make_move(0, &player, PutMove::new(cell), now).is_ok());
+        }
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, now).is_ok());
+
+        // The second game started with the opponent as cross.
+        let state = host.state_mut();
+        let second = state.games.get(&1).unwrap().clone();
+        claim_eq!(second.cross, Player::Cross(OPPONENT));
+        claim_eq!(
+            second.game_state,
+            GameState::InProgress(Player::Cross(OPPONENT))
+        );
+        claim_eq!(second.match_id, Some(0));
+
+        // The opponent resigns, so the creator has won two of three games.
+        claim!(state.games.get_mut(&1).unwrap().resign(&OPPONENT).is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 1, now).is_ok());
+        let the_match = host.state().matches.get(&0).unwrap().clone();
+        claim_eq!(the_match.games, [0, 1]);
+        claim_eq!((the_match.creator_wins, the_match.opponent_wins), (2, 0));
+        claim_eq!(the_match.match_state, MatchState::Finished(Some(INITIATOR)));
+        claim_eq!(host.state().ctr, 2);
+        claim_eq!(
+            logger.logs.last(),
+            Some(&to_bytes(&Event::MatchFinished {
+                id: 0,
+                outcome: MatchState::Finished(Some(INITIATOR)),
+            }))
+        );
+    }
+
+    #[concordium_test]
+    fn test_tournament() {
+        let now = Timestamp::from_timestamp_millis(0);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let state = State::empty(INITIATOR, &mut state_builder);
+        let mut host = TestHost::new(state, state_builder);
+        let third = AccountAddress([2u8; 32]);
+        let fourth = AccountAddress([3u8; 32]);
+        let fee = Amount::from_ccd(10);
+
+        let three_players = to_bytes(&CreateTournamentParams {
+            size: 3,
+            entry_fee: fee,
+            settings: params(),
+        });
+        ctx.set_sender(Address::Account(INITIATOR));
+        ctx.set_parameter(&three_players);
+        claim_eq!(
+            contract_create_tournament(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::InvalidTournament)
+        );
+        let four_players = to_bytes(&CreateTournamentParams {
+            size: 4,
+            entry_fee: fee,
+            settings: params(),
+        });
+        ctx.set_parameter(&four_players);
+        claim!(contract_create_tournament(&ctx, &mut host, &mut logger).is_ok());
+
+        let register_params = to_bytes(&TournamentParams { tournament_id: 0 });
+        ctx.set_parameter(&register_params);
+        claim_eq!(
+            contract_register(&ctx, &mut host, Amount::from_ccd(1), &mut logger),
+            Err(CustomContractError::WrongStake)
+        );
+        for player in [INITIATOR, OPPONENT, third] {
+            ctx.set_sender(Address::Account(player));
+            claim!(contract_register(&ctx, &mut host, fee, &mut logger).is_ok());
+        }
+        claim_eq!(
+            contract_register(&ctx, &mut host, fee, &mut logger),
+            Err(CustomContractError::AlreadyRegistered)
+        );
+        claim_eq!(host.state().ctr, 0);
+        ctx.set_sender(Address::Account(fourth));
+        claim!(contract_register(&ctx, &mut host, fee, &mut logger).is_ok());
+        host.set_self_balance(fee * 4);
+
+        // The first round pairs the players in registration order.
+        let state = host.state_mut();
+        claim_eq!(
+            state.tournaments.get(&0).unwrap().tournament_state,
+            TournamentState::InProgress { round: 1 }
+        );
+        let first = state.games.get(&0).unwrap().clone();
+        claim_eq!((first.cross, first.circle), (CROSS, Some(CIRCLE)));
+        claim_eq!(first.game_state, GameState::InProgress(CROSS));
+        claim_eq!(first.tournament_id, Some(0));
+        claim_eq!(state.games.get(&1).unwrap().cross, Player::Cross(third));
+
+        // The opponent resigns, and the second game is aborted so it is
+        // replayed with the colours swapped.
+        claim!(state.games.get_mut(&0).unwrap().resign(&OPPONENT).is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, now).is_ok());
+        let state = host.state_mut();
+        claim!(state
+            .games
+            .get_mut(&1)
+            .unwrap()
+            .propose_abort(&third)
+            .is_ok());
+        claim!(state
+            .games
+            .get_mut(&1)
+            .unwrap()
+            .accept_abort(&fourth)
+            .is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 1, now).is_ok());
+        let state = host.state_mut();
+        let replay = state.games.get(&2).unwrap().clone();
+        claim_eq!(
+            (replay.cross, replay.circle),
+            (Player::Cross(fourth), Some(Player::Circle(third)))
+        );
+
+        // The third player wins the replay and meets the initiator in the
+        // final.
+        claim!(state.games.get_mut(&2).unwrap().resign(&fourth).is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 2, now).is_ok());
+        let state = host.state_mut();
+        claim_eq!(
+            state.tournaments.get(&0).unwrap().tournament_state,
+            TournamentState::InProgress { round: 2 }
+        );
+        let last = state.games.get(&3).unwrap().clone();
+        claim_eq!(
+            (last.cross, last.circle),
+            (CROSS, Some(Player::Circle(third)))
+        );
+        claim!(state.games.get_mut(&3).unwrap().resign(&INITIATOR).is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 3, now).is_ok());
+
+        let outcome = TournamentState::Finished {
+            winner: third,
+            runner_up: INITIATOR,
+        };
+        let tournament = host.state().tournaments.get(&0).unwrap().clone();
+        claim_eq!(tournament.tournament_state, outcome);
+        claim_eq!(tournament.games, [0, 1, 2, 3]);
+        claim_eq!(
+            host.get_transfers(),
+            [
+                (third, Amount::from_ccd(30)),
+                (INITIATOR, Amount::from_ccd(10))
+            ]
+        );
+        claim_eq!(
+            logger.logs.last(),
+            Some(&to_bytes(&Event::TournamentFinished { id: 0, outcome }))
+        );
+    }
+
+    #[concordium_test]
+    fn test_pause() {
+        let mut ctx = TestReceiveContext::empty();
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let admin = AccountAddress([2u8; 32]);
+        let start = Timestamp::from_timestamp_millis(1000);
+        let late = start.checked_add(Duration::from_seconds(61)).unwrap();
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        claim!(state
+            .join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, start)
+            .is_ok());
+        let mut host = TestHost::new(state, state_builder);
+        ctx.set_self_address(SELF_ADDRESS);
+        ctx.set_metadata_slot_time(start);
+
+        // Only the owner can appoint admins.
+        let set_admin = to_bytes(&SetAdminParams {
+            account: admin,
+            is_admin: true,
+        });
+        ctx.set_parameter(&set_admin);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_set_admin(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_set_admin(&ctx, &mut host, &mut logger).is_ok());
+
+        // An admin pauses the contract, which blocks new games.
+        let pause = to_bytes(&SetPausedParams { paused: true });
+        ctx.set_parameter(&pause);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_set_paused(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Account(admin));
+        claim!(contract_set_paused(&ctx, &mut host, &mut logger).is_ok());
+        let create = to_bytes(&params());
+        ctx.set_parameter(&create);
+        claim_eq!(
+            contract_create(&ctx, &mut host, Amount::zero(), &mut logger),
+            Err(CustomContractError::ContractPaused)
+        );
+        // Moves cannot be taken back either, like they cannot be made.
+        let takeback = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&takeback);
+        claim_eq!(
+            contract_request_takeback(&ctx, &mut host),
+            Err(CustomContractError::ContractPaused)
+        );
+        // Cross cannot move, so circle cannot claim the game on time.
+        ctx.set_metadata_slot_time(late);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_claim_timeout(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::ContractPaused)
+        );
+        let roles = contract_roles(&ctx, &host).unwrap();
+        claim_eq!(roles.admins, [admin]);
+        claim!(roles.paused);
+
+        // The new owner can unpause the contract, and the old one no longer
+        // manages it.
+        let transfer = to_bytes(&TransferOwnershipParams {
+            new_owner: OPPONENT,
+        });
+        ctx.set_parameter(&transfer);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_transfer_ownership(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            contract_transfer_ownership(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::Unauthorized)
+        );
+        let unpause = to_bytes(&SetPausedParams { paused: false });
+        ctx.set_parameter(&unpause);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim!(contract_set_paused(&ctx, &mut host, &mut logger).is_ok());
+        ctx.set_parameter(&create);
+        claim!(contract_create(&ctx, &mut host, Amount::zero(), &mut logger).is_ok());
+        claim_eq!(host.state().owner, OPPONENT);
+
+        // Cross gets a full move timeout again from the moment the contract
+        // was unpaused.
+        ctx.set_parameter(&takeback);
+        claim_eq!(
+            contract_claim_timeout(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::DeadlineNotPassed)
+        );
+        let later = late.checked_add(Duration::from_seconds(61)).unwrap();
+        ctx.set_metadata_slot_time(later);
+        claim!(contract_claim_timeout(&ctx, &mut host, &mut logger).is_ok());
+    }
+
+    #[concordium_test]
+    fn test_upgrade() {
+        let mut ctx = TestReceiveContext::empty();
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let state = State::empty(INITIATOR, &mut state_builder);
+        let mut host = TestHost::new(VersionedState::Current(state), state_builder);
+        let self_address = ContractAddress::new(0, 0);
+        ctx.set_self_address(self_address);
+        let module = ModuleReference::from([1u8; 32]);
+        let missing = ModuleReference::from([2u8; 32]);
+        host.setup_mock_upgrade(module, Ok(()));
+        host.setup_mock_upgrade(missing, Err(UpgradeError::MissingModule));
+
+        let upgrade = to_bytes(&UpgradeParams {
+            module,
+            migrate: None,
+        });
+        ctx.set_parameter(&upgrade);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_upgrade(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_upgrade(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            logger.logs.last(),
+            Some(&to_bytes(&Event::Upgraded { module }))
+        );
+        let upgrade_missing = to_bytes(&UpgradeParams {
+            module: missing,
+            migrate: None,
+        });
+        ctx.set_parameter(&upgrade_missing);
+        claim_eq!(
+            contract_upgrade(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::FailedUpgrade)
+        );
+
+        // Only the contract itself and its admins can migrate the state. The
+        // current layout is left as it is.
+        ctx.set_sender(Address::Contract(ContractAddress::new(1, 0)));
+        claim_eq!(
+            contract_migrate(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Contract(self_address));
+        let log_count = logger.logs.len();
+        claim!(contract_migrate(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(logger.logs.len(), log_count);
+        let current = to_bytes(host.state());
+        let state_api = TestStateApi::new();
+        let state = VersionedState::deserial_with_state(&state_api, &mut Cursor::new(&current));
+        claim!(matches!(
+            state,
+            Ok(VersionedState::Current(State {
+                version: STATE_VERSION,
+                ..
+            }))
+        ));
+
+        // A state written by a later version is kept as it is.
+        let newer = to_bytes(&(STATE_VERSION + 1, [7u8; 16]));
+        let state = VersionedState::deserial_with_state(&state_api, &mut Cursor::new(&newer));
+        claim!(matches!(&state, Ok(VersionedState::Newer(bytes)) if *bytes == newer));
+        *host.state_mut() = state.unwrap_abort();
+        claim_eq!(
+            contract_migrate(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::UnknownStateVersion)
+        );
+        claim_eq!(to_bytes(host.state()), newer);
+    }
+
+    #[concordium_test]
+    fn test_prune_finished() {
+        let mut ctx = TestReceiveContext::empty();
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let early = Timestamp::from_timestamp_millis(1000);
+        for _ in 0..2 {
+            let game_id =
+                state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+            claim!(state
+                .join(
+                    &mut state_builder,
+                    game_id,
+                    CIRCLE,
+                    Amount::zero(),
+                    None,
+                    early
+                )
+                .is_ok());
+        }
+        let mut host = TestHost::new(state, state_builder);
+
+        // The first game ends early, the second one is still being played.
+        for (player, cell) in [(INITIATOR, 0), (OPPONENT, 3), (INITIATOR, 1)] {
+            claim!(host
+                .state_mut()
+                .make_move(0, &player, PutMove::new(cell), early)
+                .is_ok());
+        }
+        claim!(host
+            .state_mut()
+            .games
+            .get_mut(&0)
+            .unwrap()
+            .resign(&OPPONENT)
+            .is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, early).is_ok());
+
+        let prune = to_bytes(&PruneParams {
+            start: 0,
+            older_than: Duration::from_days(1),
+        });
+        ctx.set_parameter(&prune);
+        ctx.set_metadata_slot_time(early);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_prune_finished(&ctx, &mut host),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim_eq!(
+            contract_prune_finished(&ctx, &mut host),
+            Ok(PruneResult {
+                pruned: 0,
+                next: None,
+            })
+        );
+        let summary = host.state().summary(0).unwrap();
+        ctx.set_metadata_slot_time(Timestamp::from_timestamp_millis(1000 + 86_400_000));
+        claim_eq!(
+            contract_prune_finished(&ctx, &mut host),
+            Ok(PruneResult {
+                pruned: 1,
+                next: None,
+            })
+        );
+
+        // The views read the archived game like a stored one.
+        let state = host.state();
+        claim!(state.games.get(&0).is_none());
+        claim!(state.games.get(&1).is_some());
+        claim_eq!(state.summary(0), Ok(summary.clone()));
+        claim_eq!(summary.game_state, GameState::Finished(Some(CROSS)));
+        claim_eq!((summary.move_count, summary.ended), (3, early));
+        let page = state.list_games(&ListGamesParams {
+            start: 0,
+            limit: 10,
+            state: Some(GameStateKind::Finished),
+            account: Some(OPPONENT),
+        });
+        claim_eq!(page.games.len(), 1);
+        claim_eq!(page.games[0].id, 0);
+
+        // The views of the full game tell that it was archived.
+        let archived = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&archived);
+        let error = Err(Reject::from(CustomContractError::GameArchived));
+        claim_eq!(tictactoe_game_view(&ctx, &host).map(|_| ()), error);
+        claim_eq!(tictactoe_game_board(&ctx, &host).map(|_| ()), error);
+        claim_eq!(tictactoe_ultimate_board(&ctx, &host).map(|_| ()), error);
+        claim!(state.history.get(&(0, 0)).is_none());
+        let archived_history = to_bytes(&HistoryParams {
+            game_id: 0,
+            start: 0,
+            limit: 10,
+        });
+        ctx.set_parameter(&archived_history);
+        claim_eq!(tictactoe_game_history(&ctx, &host).map(|_| ()), error);
+        ctx.set_parameter(&archived);
+        claim_eq!(
+            tictactoe_game_view_players(&ctx, &host).map(|players| players.circle),
+            Ok(Some(OPPONENT))
+        );
+        claim!(!tictactoe_view(&ctx, &host).unwrap().games.contains_key(&0));
+        let missing = to_bytes(&JoinParams { game_id: 2 });
+        ctx.set_parameter(&missing);
+        claim_eq!(
+            tictactoe_game_board(&ctx, &host).map(|_| ()),
+            Err(Reject::from(CustomContractError::InvalidGameId))
+        );
+    }
+
+    #[concordium_test]
+    fn test_history() {
+        let mut ctx = TestReceiveContext::empty();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let start = Timestamp::from_timestamp_millis(1000);
+        let later = Timestamp::from_timestamp_millis(2000);
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        claim!(state
+            .join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, start)
+            .is_ok());
+
+        // Rejected moves are not recorded.
+        claim!(state
+            .make_move(0, &INITIATOR, PutMove::new(4), start)
+            .is_ok());
+        claim!(state
+            .make_move(0, &OPPONENT, PutMove::new(4), later)
+            .is_err());
+        claim!(state
+            .make_move(0, &OPPONENT, PutMove::new(0), later)
+            .is_ok());
+
+        let host = TestHost::new(state, state_builder);
+        let history_params = to_bytes(&HistoryParams {
+            game_id: 0,
+            start: 0,
+            limit: 10,
+        });
+        ctx.set_parameter(&history_params);
+        claim_eq!(
+            tictactoe_game_history(&ctx, &host),
+            Ok(HistoryPage {
+                moves: vec![
+                    MoveRecord {
+                        player: CROSS,
+                        cell: 4,
+                        made_at: start,
+                    },
+                    MoveRecord {
+                        player: CIRCLE,
+                        cell: 0,
+                        made_at: later,
+                    },
+                ],
+                next: None,
+            })
+        );
+
+        // The history is read one page at a time.
+        let first_page = to_bytes(&HistoryParams {
+            game_id: 0,
+            start: 0,
+            limit: 1,
+        });
+        ctx.set_parameter(&first_page);
+        let page = tictactoe_game_history(&ctx, &host).unwrap();
+        claim_eq!((page.moves.len(), page.next), (1, Some(1)));
+        let second_page = to_bytes(&HistoryParams {
+            game_id: 0,
+            start: 1,
+            limit: 1,
+        });
+        ctx.set_parameter(&second_page);
+        let page = tictactoe_game_history(&ctx, &host).unwrap();
+        claim_eq!(page.moves[0].cell, 0);
+        claim_eq!(page.next, None);
+    }
+
+    /// Test offering draws and taking back moves, also in ultimate games.
+    #[concordium_test]
+    fn test_draw_and_takeback() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        ctx.set_self_address(SELF_ADDRESS);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let ultimate = CreateGameParams {
+            variant: GameVariant::Ultimate,
+            ..params()
+        };
+        for settings in [params(), ultimate] {
+            let game_id =
+                state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &settings);
+            claim!(state
+                .join(
+                    &mut state_builder,
+                    game_id,
+                    CIRCLE,
+                    Amount::zero(),
+                    None,
+                    now
+                )
+                .is_ok());
+        }
+        let mut host = TestHost::new(state, state_builder);
+        let game_params = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&game_params);
+
+        // A move lets a draw offer expire, and only the opponent can accept.
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_offer_draw(&ctx, &mut host).is_ok());
+        claim_eq!(
+            contract_offer_draw(&ctx, &mut host),
+            Err(CustomContractError::OfferAlreadyMade)
+        );
+        claim!(host
+            .state_mut()
+            .make_move(0, &INITIATOR, PutMove::new(0), now)
+            .is_ok());
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_accept_draw(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::NoPendingOffer)
+        );
+
+        // Cross takes back the misclick once circle accepts.
+        claim_eq!(
+            contract_request_takeback(&ctx, &mut host),
+            Err(CustomContractError::CannotTakeBack)
+        );
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_request_takeback(&ctx, &mut host).is_ok());
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim!(contract_accept_takeback(&ctx, &mut host, &mut logger).is_ok());
+        let game = host.state().games.get(&0).unwrap().clone();
+        claim_eq!(game.board.get(0), Some(Cell::Empty));
+        claim_eq!(game.game_state, GameState::InProgress(CROSS));
+        claim_eq!(game.history_len, 0);
+        claim!(host.state().history.get(&(0, 0)).is_none());
+        claim_eq!(
+            logger.logs.last(),
+            Some(&to_bytes(&Event::MoveTakenBack {
+                id: 0,
+                player: CROSS,
+                cell: 0,
+            }))
+        );
+
+        // Circle offers a draw, which cross accepts.
+        claim!(contract_offer_draw(&ctx, &mut host).is_ok());
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_decline_draw(&ctx, &mut host).is_ok());
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim!(contract_offer_draw(&ctx, &mut host).is_ok());
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_accept_draw(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            host.state().games.get(&0).unwrap().game_state,
+            GameState::Finished(None)
+        );
+
+        // Taking back the move that won a sub-board reopens it.
+        let state = host.state_mut();
+        for (player, cell) in [
+            (INITIATOR, 36),
+            (OPPONENT, 4),
+            (INITIATOR, 37),
+            (OPPONENT, 13),
+            (INITIATOR, 38),
+        ] {
+            claim!(state.make_move(1, &player, PutMove::new(cell), now).is_ok());
+        }
+        claim_eq!(state.games.get(&1).unwrap().board.get(4), Some(Cell::Cross));
+        claim!(state.request_takeback(1, &INITIATOR).is_ok());
+        claim!(state.accept_takeback(1, &OPPONENT, now).is_ok());
+        let mut game = state.games.get(&1).unwrap().clone();
+        claim_eq!(game.history_len, 4);
+        claim_eq!(game.board.get(4), Some(Cell::Empty));
+        claim_eq!(game.game_state, GameState::InProgress(CROSS));
+        claim_eq!(game.ultimate.as_ref().unwrap().next_sub_board, Some(4));
+        claim!(game.make_move(&CROSS, PutMove::new(38)).is_ok());
+        claim_eq!(game.board.get(4), Some(Cell::Cross));
+    }
+
+    /// Test that offers and requests still pending when a game ends cannot be
+    /// accepted afterwards, which would reopen the game or pay it out twice.
+    #[concordium_test]
+    fn test_answer_after_end() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let late = now.checked_add(Duration::from_seconds(61)).unwrap();
+        let stake = Amount::from_ccd(1);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        ctx.set_self_address(SELF_ADDRESS);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        for _ in 0..2 {
+            let game_id = state.create_game(&mut state_builder, INITIATOR, stake, &params());
+            claim!(state
+                .join(&mut state_builder, game_id, CIRCLE, stake, None, now)
+                .is_ok());
+            claim!(state
+                .make_move(game_id, &INITIATOR, PutMove::new(0), now)
+                .is_ok());
+        }
+        let mut host = TestHost::new(state, state_builder);
+        host.set_self_balance(Amount::from_ccd(4));
+
+        // Cross asks to take back the move, but circle resigns instead.
+        let game_params = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&game_params);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_request_takeback(&ctx, &mut host).is_ok());
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim!(contract_resign(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            contract_accept_takeback(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::CannotTakeBack)
+        );
+        claim_eq!(
+            host.state().games.get(&0).unwrap().game_state,
+            GameState::Finished(Some(CROSS))
+        );
+
+        // Circle offers a draw but does not move, so cross wins on time.
+        let game_params = to_bytes(&JoinParams { game_id: 1 });
+        ctx.set_parameter(&game_params);
+        claim!(contract_offer_draw(&ctx, &mut host).is_ok());
+        ctx.set_metadata_slot_time(late);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_claim_timeout(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            contract_accept_draw(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::InvalidGameState)
+        );
+        // Each pot is paid out once.
+        let pot = Amount::from_ccd(2);
+        claim_eq!(host.get_transfers(), [(INITIATOR, pot), (INITIATOR, pot)]);
+
+        // Neither could be accepted even if it were still pending.
+        let mut game = host.state().games.get(&1).unwrap().clone();
+        game.draw_offer = Some(CIRCLE);
+        game.takeback_request = Some(CIRCLE);
+        claim_eq!(
+            game.answer_draw(&INITIATOR, true),
+            Err(CustomContractError::InvalidGameState)
+        );
+        claim_eq!(
+            game.accept_takeback(&INITIATOR, None, None, late),
+            Err(CustomContractError::CannotTakeBack)
+        );
+    }
+
+    /// Test bots playing against each other and against a human.
+    #[concordium_test]
+    fn test_bots() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        ctx.set_self_address(SELF_ADDRESS);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        for _ in 0..2 {
+            let game_id =
+                state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+            claim!(state
+                .join(
+                    &mut state_builder,
+                    game_id,
+                    CIRCLE,
+                    Amount::zero(),
+                    None,
+                    now
+                )
+                .is_ok());
+        }
+        let mut host = TestHost::new(state, state_builder);
+        let first_empty = ContractAddress::new(10, 0);
+        let broken = ContractAddress::new(11, 0);
+        // This bot marks the first empty cell.
+        host.setup_mock_entrypoint(
+            first_empty,
+            OwnedEntrypointName::new_unchecked("on_turn".into()),
+            MockFn::new_v1(|parameter, _, _, _: &mut State<TestStateApi>| {
+                let params: OnTurnParams = from_bytes(parameter.as_ref()).unwrap();
+                let cell = (0..9)
+                    .find(|i| params.board.get(*i) == Some(Cell::Empty))
+                    .unwrap();
+                Ok((false, cell as u64))
+            }),
+        );
+        host.setup_mock_entrypoint(
+            broken,
+            OwnedEntrypointName::new_unchecked("on_turn".into()),
+            MockFn::returning_ok(9u64),
+        );
+
+        let game_params = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&game_params);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim_eq!(
+            contract_play_bot_turn(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::NotABotTurn)
+        );
+        let use_first_empty = to_bytes(&SetBotParams {
+            bot: Some(first_empty),
+        });
+        ctx.set_parameter(&use_first_empty);
+        for account in [INITIATOR, OPPONENT] {
+            ctx.set_sender(Address::Account(account));
+            claim!(contract_set_bot(&ctx, &mut host, &mut logger).is_ok());
+        }
+
+        // Two bots play the first game until cross wins with 2, 4 and 6.
+        ctx.set_parameter(&game_params);
+        claim_eq!(contract_play_bot_turn(&ctx, &mut host, &mut logger), Ok(7));
+        let game = host.state().games.get(&0).unwrap().clone();
+        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+        claim_eq!(game.history_len, 7);
+
+        // A bot returning a cell off the board does not get to move.
+        let set_bot = to_bytes(&SetBotParams { bot: Some(broken) });
+        ctx.set_parameter(&set_bot);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_set_bot(&ctx, &mut host, &mut logger).is_ok());
+        let second_game = to_bytes(&JoinParams { game_id: 1 });
+        ctx.set_parameter(&second_game);
+        claim_eq!(
+            contract_play_bot_turn(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::BotFailed)
+        );
+
+        // The human plays in person, and the bot of the opponent replies.
+        let no_bot = to_bytes(&SetBotParams { bot: None });
+        ctx.set_parameter(&no_bot);
+        claim!(contract_set_bot(&ctx, &mut host, &mut logger).is_ok());
+        claim!(host
+            .state_mut()
+            .make_move(1, &INITIATOR, PutMove::new(4), now)
+            .is_ok());
+        ctx.set_parameter(&second_game);
+        claim_eq!(contract_play_bot_turn(&ctx, &mut host, &mut logger), Ok(1));
+        let game = host.state().games.get(&1).unwrap().clone();
+        claim_eq!(game.board.get(0), Some(Cell::Circle));
+        claim_eq!(game.game_state, GameState::InProgress(CROSS));
+    }
+
+    /// Test that nothing can change the state while a bot is called.
+    #[concordium_test]
+    fn test_bot_reentrancy() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        ctx.set_self_address(SELF_ADDRESS);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        claim!(state
+            .join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, now)
+            .is_ok());
+        let bot = ContractAddress::new(10, 0);
+        state.bots.insert(INITIATOR, bot);
+        let mut host = TestHost::new(state, state_builder);
+        // The bot only marks a cell on the board if the contract is guarded
+        // while it is called.
+        host.setup_mock_entrypoint(
+            bot,
+            OwnedEntrypointName::new_unchecked("on_turn".into()),
+            MockFn::new_v1(|_, _, _, state: &mut State<TestStateApi>| {
+                let cell: u64 = if state.bot_call_active { 4 } else { 9 };
+                Ok((false, cell))
+            }),
+        );
+        let game_params = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&game_params);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(contract_play_bot_turn(&ctx, &mut host, &mut logger), Ok(1));
+        claim!(!host.state().bot_call_active);
+
+        // The entrypoints the bot could call back into, directly or through
+        // another contract, all fail while it makes its move.
+        host.state_mut().bot_call_active = true;
+        let reentrancy = Err(CustomContractError::Reentrancy);
+        ctx.set_sender(Address::Contract(bot));
+        claim_eq!(
+            contract_play_bot_turn(&ctx, &mut host, &mut logger).map(|_| ()),
+            reentrancy
+        );
+        claim_eq!(
+            contract_transfer(&ctx, &mut host, &mut logger),
+            Err(Cis2Error::Custom(CustomContractError::Reentrancy))
+        );
+        ctx.set_sender(Address::Contract(ContractAddress::new(20, 0)));
+        claim_eq!(
+            contract_on_receiving_cis2(&ctx, &mut host, &mut logger),
+            reentrancy
+        );
+        ctx.set_sender(Address::Account(OPPONENT));
+        let make_move = to_bytes(&MakeMoveParams {
+            game_id: 0,
+            the_move: 0,
+        });
+        ctx.set_parameter(&make_move);
+        claim_eq!(contract_make_move(&ctx, &mut host, &mut logger), reentrancy);
+        claim_eq!(
+            host.state().games.get(&0).unwrap().board.get(0),
+            Some(Cell::Empty)
+        );
+    }
+
+    /// Test using permits once their signature is checked. The test host
+    /// cannot check account signatures, so 'permit_move' itself is not covered.
+    #[concordium_test]
+    fn test_permits() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let later = Timestamp::from_timestamp_millis(2000);
+        let self_address = ContractAddress::new(0, 0);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        claim!(state
+            .join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, now)
+            .is_ok());
+        let mut host = TestHost::new(state, state_builder);
+
+        let permit = |nonce, the_move| PermitMessage {
+            contract_address: self_address,
+            nonce,
+            expiry: now,
+            payload: MakeMoveParams {
+                game_id: 0,
+                the_move,
+            },
+        };
+        claim_eq!(
+            use_permit(
+                &mut host,
+                &mut logger,
+                ContractAddress::new(1, 0),
+                INITIATOR,
+                &permit(0, 4),
+                now
+            ),
+            Err(CustomContractError::WrongContract)
+        );
+        claim_eq!(
+            use_permit(
+                &mut host,
+                &mut logger,
+                self_address,
+                INITIATOR,
+                &permit(0, 4),
+                later
+            ),
+            Err(CustomContractError::PermitExpired)
+        );
+        claim!(use_permit(
+            &mut host,
+            &mut logger,
+            self_address,
+            INITIATOR,
+            &permit(0, 4),
+            now
+        )
+        .is_ok());
+        claim_eq!(
+            host.state().games.get(&0).unwrap().board.get(4),
+            Some(Cell::Cross)
+        );
+        claim_eq!(host.state().nonce_of(&INITIATOR), 1);
+
+        // A permit cannot be used twice, and nonces count per account.
+        claim_eq!(
+            use_permit(
+                &mut host,
+                &mut logger,
+                self_address,
+                INITIATOR,
+                &permit(0, 4),
+                now
+            ),
+            Err(CustomContractError::WrongNonce)
+        );
+        claim!(use_permit(
+            &mut host,
+            &mut logger,
+            self_address,
+            OPPONENT,
+            &permit(0, 0),
+            now
+        )
+        .is_ok());
+        claim_eq!(
+            host.state().games.get(&0).unwrap().game_state,
+            GameState::InProgress(CROSS)
+        );
+    }
+
+    /// Test closing and disputing games played in a channel.
+    #[concordium_test]
+    fn test_channel() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let after_window = Timestamp::from_timestamp_millis(62000);
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        let settings = CreateGameParams {
+            channel: true,
+            ..params()
+        };
+        for game_id in 0..2 {
+            state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &settings);
+            claim!(state
+                .join(
+                    &mut state_builder,
+                    game_id,
+                    CIRCLE,
+                    Amount::zero(),
+                    None,
+                    now
+                )
+                .is_ok());
+        }
+        let position = |game_id, crosses: &[usize], circles: &[usize]| {
+            let mut board = Board::new(3, 3);
+            crosses.iter().for_each(|i| board.set(*i, Cell::Cross));
+            circles.iter().for_each(|i| board.set(*i, Cell::Circle));
+            ChannelState {
+                contract_address: ContractAddress::new(0, 0),
+                game_id,
+                move_count: (crosses.len() + circles.len()) as u32,
+                board,
+            }
+        };
+
+        // The moves are only played off-chain, and the final state ends the game.
+        claim_eq!(
+            state.make_move(0, &INITIATOR, PutMove::new(0), now),
+            Err(CustomContractError::ChannelOpen)
+        );
+        let mut game = state.games.get(&0).unwrap().clone();
+        claim_eq!(
+            game.close_channel(position(0, &[0, 1], &[]), now),
+            Err(CustomContractError::InvalidChannelState)
+        );
+        claim_eq!(
+            game.close_channel(position(0, &[0, 1], &[4]), now),
+            Err(CustomContractError::ChannelNotFinished)
+        );
+        claim!(game
+            .close_channel(position(0, &[0, 1, 2], &[4, 5]), now)
+            .is_ok());
+        claim_eq!(game.game_state, GameState::Finished(Some(CROSS)));
+
+        // Circle disputes, but cross still gets to submit a newer state.
+        let mut game = state.games.get_mut(&1).unwrap();
+        claim!(game
+            .dispute(&OPPONENT, Some(position(1, &[0], &[4])), now)
+            .is_ok());
+        claim_eq!(game.game_state, GameState::InProgress(CROSS));
+        claim_eq!(
+            game.dispute(&INITIATOR, Some(position(1, &[0], &[4])), now),
+            Err(CustomContractError::StaleChannelState)
+        );
+        claim!(game
+            .dispute(&INITIATOR, Some(position(1, &[0, 1], &[4])), now)
+            .is_ok());
+        claim_eq!(game.game_state, GameState::InProgress(CIRCLE));
+        claim_eq!(
+            game.dispute(&OPPONENT, None, after_window),
+            Err(CustomContractError::ChallengeWindowClosed)
+        );
+
+        // A disputed channel only closes during the challenge window, with a
+        // newer state that keeps the marks on-chain.
+        let finished = position(1, &[0, 1, 2], &[4, 5]);
+        let mut closed = (*game).clone();
+        claim_eq!(
+            closed.close_channel(finished.clone(), after_window),
+            Err(CustomContractError::ChallengeWindowClosed)
+        );
+        claim_eq!(
+            closed.close_channel(position(1, &[0, 3, 6], &[4, 5]), now),
+            Err(CustomContractError::InvalidChannelState)
+        );
+        claim!(closed.close_channel(finished, now).is_ok());
+        claim_eq!(closed.game_state, GameState::Finished(Some(CROSS)));
+        drop(game);
+
+        // After the challenge window the game goes on with on-chain moves.
+        claim_eq!(
+            state.make_move(1, &OPPONENT, PutMove::new(2), now),
+            Err(CustomContractError::ChallengeWindowOpen)
+        );
+        claim!(state
+            .make_move(1, &OPPONENT, PutMove::new(2), after_window)
+            .is_ok());
+        let game = state.games.get(&1).unwrap();
+        claim_eq!(game.game_state, GameState::InProgress(CROSS));
+        claim_eq!(
+            game.channel.as_ref().map(|channel| channel.move_count),
+            Some(4)
+        );
+    }
+
+    /// Test that winners get a CIS-2 trophy they can transfer.
+    #[concordium_test]
+    fn test_trophies() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let mut ctx = TestReceiveContext::empty();
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        claim!(state
+            .join(&mut state_builder, 0, CIRCLE, Amount::zero(), None, now)
+            .is_ok());
+        for (player, cell) in [(INITIATOR, 0), (OPPONENT, 3), (INITIATOR, 1), (OPPONENT, 4)] {
+            claim!(state.make_move(0, &player, PutMove::new(cell), now).is_ok());
+        }
+        claim!(state.make_move(0, &INITIATOR, PutMove::new(2), now).is_ok());
+        let mut host = TestHost::new(state, state_builder);
+
+        // Only the owner sets where the metadata is found.
+        let base_url = to_bytes(&SetMetadataUrlParams {
+            base_url: "https://example.com/trophies/".into(),
+        });
+        ctx.set_parameter(&base_url);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_set_metadata_url(&ctx, &mut host),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_set_metadata_url(&ctx, &mut host).is_ok());
+
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, now).is_ok());
+        let minted = to_bytes(&Event::TrophyMinted {
+            token_id: TokenId(0),
+            amount: TokenAmount(1),
+            owner: Address::Account(INITIATOR),
+        });
+        let metadata_url = MetadataUrl {
+            url: "https://example.com/trophies/0000000000000000".into(),
+            hash: None,
+        };
+        let metadata = to_bytes(&Event::TokenMetadata {
+            token_id: TokenId(0),
+            metadata_url: metadata_url.clone(),
+        });
+        claim_eq!((minted[0], metadata[0]), (254, 251));
+        claim_eq!(logger.logs[logger.logs.len() - 2..], [minted, metadata][..]);
+        claim_eq!(to_bytes(&TokenAmount(300)), [0xac, 0x02]);
+
+        let cross = Address::Account(INITIATOR);
+        let circle = Address::Account(OPPONENT);
+        let balances = to_bytes(&BalanceOfQueryParams(vec![
+            cis2::BalanceOfQuery {
+                token_id: TokenId(0),
+                address: cross,
+            },
+            cis2::BalanceOfQuery {
+                token_id: TokenId(0),
+                address: circle,
+            },
+        ]));
+        ctx.set_parameter(&balances);
+        claim_eq!(
+            tictactoe_balance_of(&ctx, &host),
+            Ok(BalanceOfQueryResponse(vec![TokenAmount(1), TokenAmount(0)]))
+        );
+        let token_ids = to_bytes(&TokenMetadataQueryParams(vec![TokenId(0)]));
+        ctx.set_parameter(&token_ids);
+        claim_eq!(
+            tictactoe_token_metadata(&ctx, &host),
+            Ok(TokenMetadataQueryResponse(vec![metadata_url]))
+        );
+        let missing = to_bytes(&TokenMetadataQueryParams(vec![TokenId(1)]));
+        ctx.set_parameter(&missing);
+        claim_eq!(
+            tictactoe_token_metadata(&ctx, &host),
+            Err(Cis2Error::InvalidTokenId)
+        );
+
+        // The metadata is served from the trophy.
+        let game = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&game);
+        let trophy = tictactoe_trophy(&ctx, &host).unwrap();
+        claim_eq!(trophy.owner, cross);
+        claim_eq!(trophy.opponent, OPPONENT);
+        claim_eq!(trophy.board.get(2), Some(Cell::Cross));
+        let game = to_bytes(&JoinParams { game_id: 1 });
+        ctx.set_parameter(&game);
+        claim_eq!(
+            tictactoe_trophy(&ctx, &host).map(|_| ()),
+            Err(CustomContractError::InvalidTokenId.into())
+        );
+
+        // Without a base URL there is no metadata to point to.
+        let no_url = to_bytes(&SetMetadataUrlParams {
+            base_url: String::new(),
+        });
+        ctx.set_parameter(&no_url);
+        ctx.set_sender(cross);
+        claim!(contract_set_metadata_url(&ctx, &mut host).is_ok());
+        ctx.set_parameter(&token_ids);
+        claim_eq!(
+            tictactoe_token_metadata(&ctx, &host),
+            Err(Cis2Error::Custom(CustomContractError::MetadataUrlNotSet))
+        );
+
+        // Only the owner or their operators can move the trophy.
+        let transfer = |from| {
+            to_bytes(&TransferParams(vec![Transfer {
+                token_id: TokenId(0),
+                amount: TokenAmount(1),
+                from,
+                to: Receiver::Account(OPPONENT),
+                data: cis2::AdditionalData(Vec::new()),
+            }]))
+        };
+        let from_cross = transfer(cross);
+        ctx.set_parameter(&from_cross);
+        ctx.set_sender(circle);
+        claim_eq!(
+            contract_transfer(&ctx, &mut host, &mut logger),
+            Err(Cis2Error::Unauthorized)
+        );
+        let update = to_bytes(&UpdateOperatorParams(vec![UpdateOperator {
+            update: OperatorUpdate::Add,
+            operator: circle,
+        }]));
+        ctx.set_parameter(&update);
+        ctx.set_sender(cross);
+        claim!(contract_update_operator(&ctx, &mut host, &mut logger).is_ok());
+        ctx.set_parameter(&from_cross);
+        ctx.set_sender(circle);
+        claim!(contract_transfer(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            host.state().balance_of(TokenId(0), &circle),
+            Ok(TokenAmount(1))
+        );
+        claim_eq!(
+            contract_transfer(&ctx, &mut host, &mut logger),
+            Err(Cis2Error::InsufficientFunds)
+        );
+
+        // The errors of the standard have the codes it fixes.
+        for (error, code) in [
+            (CustomContractError::InvalidTokenId, -42000001),
+            (CustomContractError::InsufficientFunds, -42000002),
+            (CustomContractError::Unauthorized, -42000003),
+        ] {
+            claim_eq!(Reject::from(Cis2Error::from(error)).error_code.get(), code);
+        }
+    }
+
+    /// Test staking a game in CIS-2 tokens and paying them out.
+    #[concordium_test]
+    fn test_token_stakes() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let token = ContractAddress::new(7, 0);
+        let mut ctx = TestReceiveContext::empty();
+        ctx.set_metadata_slot_time(now);
+        ctx.set_self_address(SELF_ADDRESS);
+        let mut logger = TestLogger::init();
+        let mut state_builder = TestStateBuilder::new();
+        let state = State::empty(INITIATOR, &mut state_builder);
+        let mut host = TestHost::new(state, state_builder);
+        let received = |from, amount, action: &TokenStakeAction| {
+            to_bytes(&OnReceivingCis2Params {
+                token_id: TokenIdVec(vec![1]),
+                amount: TokenAmount(amount),
+                from: Address::Account(from),
+                data: AdditionalData(to_bytes(action)),
+            })
+        };
+        let join = TokenStakeAction::JoinGame(JoinGameParams {
+            game_id: 0,
+            commitment: None,
+        });
+
+        // Only token contracts can stake tokens.
+        let create = received(INITIATOR, 50, &TokenStakeAction::CreateGame(params()));
+        ctx.set_parameter(&create);
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim_eq!(
+            contract_on_receiving_cis2(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::NotATokenContract)
+        );
+
+        // Only the tokens the admins allowed can be staked.
+        ctx.set_sender(Address::Contract(token));
+        claim_eq!(
+            contract_on_receiving_cis2(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::TokenNotAllowed)
+        );
+        let allow = to_bytes(&SetTokenAllowedParams {
+            contract: token,
+            token_id: TokenIdVec(vec![1]),
+            allowed: true,
+        });
+        ctx.set_parameter(&allow);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_set_token_allowed(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::Unauthorized)
+        );
+        ctx.set_sender(Address::Account(INITIATOR));
+        claim!(contract_set_token_allowed(&ctx, &mut host, &mut logger).is_ok());
+        ctx.set_parameter(&create);
+        ctx.set_sender(Address::Contract(ContractAddress::new(8, 0)));
+        claim_eq!(
+            contract_on_receiving_cis2(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::TokenNotAllowed)
+        );
+        ctx.set_sender(Address::Contract(token));
+        claim!(contract_on_receiving_cis2(&ctx, &mut host, &mut logger).is_ok());
+
+        // The opponent has to match the stake in the same token.
+        let too_little = received(OPPONENT, 40, &join);
+        ctx.set_parameter(&too_little);
+        claim_eq!(
+            contract_on_receiving_cis2(&ctx, &mut host, &mut logger),
+            Err(CustomContractError::WrongStake)
+        );
+        let in_ccd = to_bytes(&JoinGameParams {
+            game_id: 0,
+            commitment: None,
+        });
+        ctx.set_parameter(&in_ccd);
+        ctx.set_sender(Address::Account(OPPONENT));
+        claim_eq!(
+            contract_join(&ctx, &mut host, Amount::zero(), &mut logger),
+            Err(CustomContractError::WrongStake)
+        );
+        let matching = received(OPPONENT, 50, &join);
+        ctx.set_parameter(&matching);
+        ctx.set_sender(Address::Contract(token));
+        claim!(contract_on_receiving_cis2(&ctx, &mut host, &mut logger).is_ok());
+        claim_eq!(
+            host.state().games.get(&0).unwrap().game_state,
+            GameState::InProgress(CROSS)
+        );
+
+        // The winner is paid the whole pot by the token contract.
+        host.setup_mock_entrypoint(
+            token,
+            OwnedEntrypointName::new_unchecked("transfer".into()),
+            MockFn::new_v1(|parameter, _, _, _: &mut State<TestStateApi>| {
+                let TransferParams::<TokenIdVec>(transfers) =
+                    from_bytes(parameter.as_ref()).unwrap();
+                claim_eq!(transfers.len(), 1);
+                claim_eq!(transfers[0].token_id, TokenIdVec(vec![1]));
+                claim_eq!(transfers[0].amount, TokenAmount(100));
+                claim_eq!(transfers[0].from, Address::Contract(SELF_ADDRESS));
+                claim_eq!(transfers[0].to.address(), Address::Account(INITIATOR));
+                Ok((false, ()))
+            }),
+        );
+        let state = host.state_mut();
+        for (player, cell) in [(INITIATOR, 0), (OPPONENT, 3), (INITIATOR, 1), (OPPONENT, 4)] {
+            claim!(state.make_move(0, &player, PutMove::new(cell), now).is_ok());
+        }
+        claim!(state.make_move(0, &INITIATOR, PutMove::new(2), now).is_ok());
+        claim!(settle(&mut host, &mut logger, SELF_ADDRESS, 0, now).is_ok());
+        claim!(host.get_transfers().is_empty());
+    }
+
+    /// Test the views that describe a game in a compact form.
+    #[concordium_test]
+    fn test_views() {
+        let now = Timestamp::from_timestamp_millis(1000);
+        let mut ctx = TestReceiveContext::empty();
+        let mut state_builder = TestStateBuilder::new();
+        let mut state = State::empty(INITIATOR, &mut state_builder);
+        state.create_game(&mut state_builder, INITIATOR, Amount::zero(), &params());
+        let mut host = TestHost::new(state, state_builder);
+        let game_params = to_bytes(&JoinParams { game_id: 0 });
+        ctx.set_parameter(&game_params);
+        claim_eq!(
+            tictactoe_game_view_players(&ctx, &host),
+            Ok(Players {
+                cross: INITIATOR,
+                circle: None,
+            })
+        );
+
+        let (state, state_builder) = host.state_and_builder();
+        claim!(state
+            .join(state_builder, 0, CIRCLE, Amount::zero(), None, now)
+            .is_ok());
+        claim!(state.make_move(0, &INITIATOR, PutMove::new(4), now).is_ok());
+        claim_eq!(
+            tictactoe_game_view_players(&ctx, &host),
+            Ok(Players {
+                cross: INITIATOR,
+                circle: Some(OPPONENT),
+            })
+        );
+        // Circle to move, cross in the center and the version on top.
+        claim_eq!(
+            tictactoe_game_view(&ctx, &host),
+            Ok(0x2 | 0b01 << (4 + 2 * 4) | COMPACT_GAME_VERSION << 28)
+        );
     }
 }
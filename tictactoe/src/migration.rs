@@ -0,0 +1,76 @@
+//! The state as seen by 'upgrade' and 'migrate'. When a change to the layout
+//! of the state, [Game](crate::Game) or [Board](crate::Board) bumps
+//! [STATE_VERSION], the previous layout is kept here as another variant of
+//! [VersionedState], so that 'migrate' can still read a state that was written
+//! before the upgrade.
+use concordium_std::*;
+
+use crate::{ContractResult, CustomContractError, State, STATE_VERSION};
+
+/// The state as the contract understands it when it is upgraded or migrated.
+/// Every other entrypoint expects the current layout.
+pub(crate) enum VersionedState<S> {
+    Current(State<S>),
+    /// A state written by a later version of the contract, kept as the bytes
+    /// of the root entry so they are written back unchanged. This is what an
+    /// 'upgrade' sees once the new module has migrated the state.
+    Newer(Vec<u8>),
+}
+
+impl<S: HasStateApi> VersionedState<S> {
+    pub(crate) fn is_admin(&self, addr: &AccountAddress) -> bool {
+        match self {
+            VersionedState::Current(state) => state.is_admin(addr),
+            VersionedState::Newer(_) => false,
+        }
+    }
+
+    pub(crate) fn ensure_no_bot_call(&self) -> ContractResult<()> {
+        match self {
+            VersionedState::Current(state) => state.ensure_no_bot_call(),
+            VersionedState::Newer(_) => Ok(()),
+        }
+    }
+
+    /// Convert the state to the current layout. Returns the version it had.
+    pub(crate) fn migrate(&mut self) -> ContractResult<u32> {
+        match self {
+            VersionedState::Current(state) => Ok(state.version),
+            VersionedState::Newer(_) => Err(CustomContractError::UnknownStateVersion),
+        }
+    }
+}
+
+impl<S: HasStateApi> Serial for VersionedState<S> {
+    fn serial<W: Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        match self {
+            VersionedState::Current(state) => state.serial(out),
+            VersionedState::Newer(bytes) => out.write_all(bytes),
+        }
+    }
+}
+
+impl<S: HasStateApi> DeserialWithState<S> for VersionedState<S> {
+    /// Every version stores its version first, so it decides how the rest of
+    /// the root entry is read.
+    fn deserial_with_state<R: Read>(state: &S, source: &mut R) -> ParseResult<Self> {
+        let mut bytes = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            let read = source.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..read]);
+        }
+        let mut cursor = Cursor::new(&bytes[..]);
+        match u32::deserial(&mut cursor)? {
+            STATE_VERSION => {
+                cursor.offset = 0;
+                State::deserial_with_state(state, &mut cursor).map(VersionedState::Current)
+            }
+            version if version > STATE_VERSION => Ok(VersionedState::Newer(bytes)),
+            _ => Err(ParseError::default()),
+        }
+    }
+}
@@ -6,28 +6,26 @@ interface BoardProps {
 }
 
 export const Board = ({cells, onCellClick}: BoardProps) => {
+    // Boards are square, so the number of cells gives the number of rows.
+    const size = Math.round(Math.sqrt(cells.length));
+
     function renderCell(i : number) {
         return (
-            <button className="board-cell" onClick={() => onCellClick(i)}>{cells[i]}</button>
+            <button key={i} className="board-cell" onClick={() => onCellClick(i)}>{cells[i]}</button>
+        )
+    }
+
+    function renderRow(row : number) {
+        return (
+            <div key={row} className="board-row">
+                {Array.from({ length: size }, (_, column) => renderCell(row * size + column))}
+            </div>
         )
     }
+
     return (
         <div>
-            <div className="board-row">
-                {renderCell(0)}
-                {renderCell(1)}
-                {renderCell(2)}
-            </div>
-            <div className="board-row">
-                {renderCell(3)}
-                {renderCell(4)}
-                {renderCell(5)}
-            </div>
-            <div className="board-row">
-                {renderCell(6)}
-                {renderCell(7)}
-                {renderCell(8)}
-            </div>
+            {Array.from({ length: size }, (_, row) => renderRow(row))}
         </div>
     )
 };
@@ -0,0 +1,57 @@
+import { detectConcordiumProvider } from '@concordium/browser-wallet-api-helpers';
+import React, { useContext, useEffect, useState } from 'react';
+import { CONTRACT_NAME, GamePage, describeGameState, deserializeGamePage, serializeListGamesParams, state } from './model'
+
+const PAGE_SIZE = 10;
+
+type GameListProps = {
+    onSelect: (gameId: bigint) => void
+}
+
+export default function GameList({ onSelect }: GameListProps) {
+    const { contractAddress } = useContext(state);
+
+    // The start of every page we have visited, so we can go back.
+    const [starts, updateStarts] = useState<bigint[]>([0n]);
+    const [page, updatePage] = useState<GamePage>({ games: [] });
+
+    const start = starts[starts.length - 1];
+
+    useEffect(() => {
+        loadPage(start).then(updatePage).catch(console.error);
+    }, [start]);
+
+    async function loadPage(from: bigint): Promise<GamePage> {
+        const provider = await detectConcordiumProvider();
+        const res = await provider.getJsonRpcClient().invokeContract(
+            {
+                method: `${CONTRACT_NAME}.list_games`,
+                contract: contractAddress,
+                parameter: serializeListGamesParams(from, PAGE_SIZE),
+            }
+        );
+        if (!res || res.tag === 'failure' || !res.returnValue) {
+            throw new Error(`Expected successful invocation`);
+        }
+        return deserializeGamePage(res.returnValue);
+    }
+
+    return (
+        <div>
+            <table>
+                <tbody>
+                    {page.games.map((game) => (
+                        <tr key={game.id.toString()} onClick={() => onSelect(game.id)}>
+                            <td>#{game.id.toString()}</td>
+                            <td>{describeGameState(game.gameState)}</td>
+                            <td>{game.cross}</td>
+                            <td>{game.circle ?? ''}</td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+            <button type="button" disabled={starts.length === 1} onClick={() => updateStarts(starts.slice(0, -1))}>Previous</button>
+            <button type="button" disabled={page.next === undefined} onClick={() => page.next !== undefined && updateStarts([...starts, page.next])}>Next</button>
+        </div>
+    )
+}
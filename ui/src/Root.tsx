@@ -5,6 +5,7 @@ import { Board } from './Board';
 
 import { detectConcordiumProvider } from '@concordium/browser-wallet-api-helpers';
 import GameState from './GameState';
+import GameList from './GameList';
 
 const CONTRACT_ADDRESS = {index: 1075n, subindex: 0n};
 
@@ -12,6 +13,7 @@ const CONTRACT_ADDRESS = {index: 1075n, subindex: 0n};
 export default function Root() {
     const [account, setAccount] = useState<string>();
     const [isConnected, setIsConnected] = useState<boolean>(false);
+    const [gameId, setGameId] = useState<bigint>(0n);
 
     const handleGetAccount = useCallback((accountAddress: string | undefined) => {
         setAccount(accountAddress);
@@ -57,7 +59,8 @@ export default function Root() {
                     )}
                 </div>
                 <div>Hello world!</div>
-                <GameState gameId={0n}></GameState>
+                <GameList onSelect={setGameId}></GameList>
+                <GameState gameId={gameId}></GameState>
             </main>
         </state.Provider>
     )
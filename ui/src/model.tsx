@@ -1,5 +1,5 @@
-
-import { ContractAddress } from '@concordium/web-sdk';
+import { AccountAddress, ContractAddress } from '@concordium/web-sdk';
+import { Buffer } from 'buffer/';
 import { createContext } from 'react';
 
 
@@ -10,4 +10,120 @@ export type State = {
 
 export const state = createContext<State>( {isConnected: false, contractAddress: {index: 1075n, subindex: 0n}} );
 
-export const CONTRACT_NAME = "tictactoe";
\ No newline at end of file
+export const CONTRACT_NAME = "tictactoe";
+
+export type Player = { mark: 'cross' | 'circle', account: string };
+
+export type GameState =
+    | { tag: 'awaitingOpponent' }
+    | { tag: 'inProgress', turn: Player }
+    | { tag: 'finished', winner?: Player }
+    | { tag: 'cancelled' }
+    | { tag: 'aborted' }
+    | { tag: 'awaitingReveal' };
+
+/** A short description of a game, as returned by `list_games`. */
+export type GameListing = {
+    id: bigint;
+    gameState: GameState;
+    cross: string;
+    circle?: string;
+    stake: bigint;
+}
+
+/** One page of games. `next` is where to continue listing from, if there are more games. */
+export type GamePage = {
+    games: GameListing[];
+    next?: bigint;
+}
+
+/** Reads values in the binary format used by the contract. */
+class Reader {
+    private offset = 0;
+
+    constructor(private readonly buf: Buffer) {}
+
+    u8(): number {
+        const value = this.buf.readUInt8(this.offset);
+        this.offset += 1;
+        return value;
+    }
+
+    u32(): number {
+        const value = this.buf.readUInt32LE(this.offset);
+        this.offset += 4;
+        return value;
+    }
+
+    u64(): bigint {
+        const value = this.buf.readBigUInt64LE(this.offset);
+        this.offset += 8;
+        return value;
+    }
+
+    account(): string {
+        const bytes = this.buf.subarray(this.offset, this.offset + 32);
+        this.offset += 32;
+        return AccountAddress.fromBytes(bytes).address;
+    }
+
+    option<T>(read: () => T): T | undefined {
+        return this.u8() === 0 ? undefined : read();
+    }
+
+    player(): Player {
+        const mark = this.u8() === 0 ? 'cross' : 'circle';
+        return { mark, account: this.account() };
+    }
+
+    gameState(): GameState {
+        const tag = this.u8();
+        switch (tag) {
+            case 0: return { tag: 'awaitingOpponent' };
+            case 1: return { tag: 'inProgress', turn: this.player() };
+            case 2: return { tag: 'finished', winner: this.option(() => this.player()) };
+            case 3: return { tag: 'cancelled' };
+            case 4: return { tag: 'aborted' };
+            case 5: return { tag: 'awaitingReveal' };
+            default: throw new Error(`Unknown game state ${tag}`);
+        }
+    }
+}
+
+/** Serialize the parameter of `list_games`, without any filters. */
+export function serializeListGamesParams(start: bigint, limit: number): Buffer {
+    const buf = Buffer.alloc(14);
+    buf.writeBigUInt64LE(start, 0);
+    buf.writeUInt32LE(limit, 8);
+    buf.writeUInt8(0, 12); // no state filter
+    buf.writeUInt8(0, 13); // no account filter
+    return buf;
+}
+
+/** Deserialize the return value of `list_games`. */
+export function deserializeGamePage(returnValue: string): GamePage {
+    const reader = new Reader(Buffer.from(returnValue, 'hex'));
+    const games: GameListing[] = [];
+    const length = reader.u32();
+    for (let i = 0; i < length; i++) {
+        games.push({
+            id: reader.u64(),
+            gameState: reader.gameState(),
+            cross: reader.account(),
+            circle: reader.option(() => reader.account()),
+            stake: reader.u64(),
+        });
+    }
+    return { games, next: reader.option(() => reader.u64()) };
+}
+
+export function describeGameState(gameState: GameState): string {
+    switch (gameState.tag) {
+        case 'awaitingOpponent': return 'Awaiting opponent';
+        case 'inProgress': return `${gameState.turn.mark} to move`;
+        case 'finished': return gameState.winner ? `${gameState.winner.mark} won` : 'Draw';
+        case 'cancelled': return 'Cancelled';
+        case 'aborted': return 'Aborted';
+        case 'awaitingReveal': return 'Drawing who plays first';
+    }
+}